@@ -3,14 +3,15 @@ use std::simd::{u8x16, u8x64, u32x4};
 
 use bevy::{
     ecs::component::Component,
-    math::{IVec3, UVec3},
+    math::{IVec3, UVec2, UVec3},
 };
 use strum_macros::EnumIter;
 
-use crate::fast_voxels::blocks::BlockID;
+use crate::fast_voxels::blocks::{BlockID, BlockState};
+use crate::fast_voxels::chunk_storage::{ChunkData, PalettedStorage, StateStorage};
 use crate::fast_voxels::mesh_gen::ChunkBitMask;
 
-pub type BlockData = Arc<[[[BlockID;CHUNKSIZE];CHUNKSIZE];CHUNKSIZE]>;
+pub type BlockData = Arc<ChunkData>;
 
 /// defines the chunk size, by the length of one side of the chunk
 pub const CHUNKSIZE: usize = 32;
@@ -28,6 +29,17 @@ pub enum Direction {
     Front,
     Back,
 }
+impl Direction {
+    /// the two axes that span a face pointing this way, in the order
+    /// ``Quad::size`` counts them
+    pub fn axes(self) -> (IVec3, IVec3) {
+        match self {
+            Direction::Top | Direction::Bottom => (IVec3::X, IVec3::Z),
+            Direction::Left | Direction::Right => (IVec3::Y, IVec3::Z),
+            Direction::Front | Direction::Back => (IVec3::X, IVec3::Y),
+        }
+    }
+}
 /// cast the direction enum to a usize and index this array to get the direction
 /// as a vector
 pub const DIRECTION_VECS: [IVec3; 6] = [
@@ -45,25 +57,36 @@ pub struct Chunk {
     pub data: BlockData,
     pub pos: IVec3,
 }
-const LEN: usize = (FAST_CHUNKSIZE+2)*(FAST_CHUNKSIZE+2)*(FAST_CHUNKSIZE+2)/2;
+// a padded fast chunk has as many voxels as a chunk, so it can reuse the paletted storage
+const _: () = assert!(FAST_CHUNKSIZE + 2 == CHUNKSIZE);
 
-/// each u32x4 is one row
-pub type FastBlockData = [u8; LEN];
-/// same as the Chunk type but uses simd to make it faster
-#[derive(Debug, Clone, Copy, Component)]
-pub struct FastChunk { // presumes each block is 4 bits
+/// each voxel is an index into the chunk's palette, so the bits per voxel
+/// grow with the number of different blocks in the chunk
+pub type FastBlockData = PalettedStorage;
+/// same as the Chunk type but uses simd to make it faster.
+/// fast chunks have their own grid: the one at ``pos`` owns the FAST_CHUNKSIZE³ world voxels
+/// starting at ``pos * FAST_CHUNKSIZE``, and stores them with a one voxel apron copied
+/// from its neighbours, so meshing never has to look outside of it
+#[derive(Debug, Clone, Component)]
+pub struct FastChunk {
     pub pos: IVec3,
     pub data: FastBlockData,
+    pub states: StateStorage,
 }
 impl FastChunk {
     pub fn get_mask(&self, block: BlockID) -> ChunkBitMask {
-        let ret: ChunkBitMask = ChunkBitMask {
-            data: [0; (FAST_CHUNKSIZE+2)*(FAST_CHUNKSIZE+2)],
-        };
-
-        for (index, block) in ret.data.iter().enumerate() {
-            for i in 0..(FAST_CHUNKSIZE+2).midpoint(0) {
-                
+        match self.data.palette().iter().position(|entry| *entry == block) {
+            Some(palette_index) => self.get_index_mask(palette_index),
+            None => ChunkBitMask::new(),
+        }
+    }
+    /// each row of the mask is one x, y column of the chunk, with bit z set
+    /// where the voxel uses the palette entry ``palette_index``
+    pub fn get_index_mask(&self, palette_index: usize) -> ChunkBitMask {
+        let mut ret: ChunkBitMask = ChunkBitMask::new();
+        for (voxel, index) in self.data.indices().enumerate() {
+            if index == palette_index {
+                ret.data[voxel / (FAST_CHUNKSIZE+2)] |= 1 << (voxel % (FAST_CHUNKSIZE+2));
             }
         }
         ret
@@ -72,6 +95,7 @@ impl FastChunk {
         Self {
             pos,
             data,
+            states: StateStorage::Empty,
         }
     }
 }
@@ -81,11 +105,18 @@ impl FastChunk {
 #[derive(Debug,Clone, Copy)]
 pub struct Quad {
     pub pos: UVec3,
+    /// how high the voxel is in ``BlockState::FLUID_LEVELS``, lower than that for liquids that arent full
+    pub height: u8,
+    /// how many voxels the quad covers along the ``Direction::axes`` of its face, starting at ``pos``.
+    /// one by one, unless greedy meshing merged faces or the chunk has a lower level of detail
+    pub size: UVec2,
 }
 impl Quad {
     pub fn new(pos: UVec3) -> Self {
         Self {
             pos,
+            height: BlockState::FLUID_LEVELS,
+            size: UVec2::ONE,
         }
     }
 }
@@ -105,26 +136,6 @@ impl VoxelMesh {
             })
         }
     }
-    pub fn get_block(data: [[[&Chunk; 3]; 3]; 3], mut block_index: IVec3) -> BlockID {
-        let mut chunk_index: UVec3 = UVec3::new(1,1,1);
-        chunk_index.x -= u32::from(block_index.x < 0);
-        chunk_index.y -= u32::from(block_index.y < 0);
-        chunk_index.z -= u32::from(block_index.z < 0);
-
-        chunk_index.x += u32::from(block_index.x > 15);
-        chunk_index.y += u32::from(block_index.y > 15);
-        chunk_index.z += u32::from(block_index.z > 15);
-
-        block_index.x &= 15;
-        block_index.y &= 15;
-        block_index.z &= 15;
-        data[chunk_index.x as usize]
-            [chunk_index.y as usize]
-            [chunk_index.z as usize]
-            .data[block_index.x as usize]
-                [block_index.y as usize]
-                [block_index.z as usize]
-    }
 }
 
 /// Contains one side of a VoxelMesh, which can be sent directly to a GPU
@@ -0,0 +1,278 @@
+use std::{io, ops::Deref, sync::Arc};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fast_voxels::{
+        block_registry::BlockRegistry,
+        blocks::BlockID,
+        world_gen::{fbm, hash},
+        world_settings::WorldSettings,
+    },
+    player::camera::Player,
+};
+
+/// the most biomes a ``BiomeMap`` can have, so weighing them doesnt allocate for every column
+pub const MAX_BIOMES: usize = 16;
+
+/// the kinds of land ``BiomeMap`` divides the world into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Highlands,
+    Forest,
+    Wetlands,
+}
+
+/// how one biome looks, and the climate it grows in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BiomeParams {
+    pub biome: Biome,
+    /// where the biome lies on the climate map, each from about -0.5 to 0.5
+    pub temperature: f32,
+    pub humidity: f32,
+    /// how far the biome's surface is centred above ``TerrainSettings::height``
+    pub height: i32,
+    /// how many times ``TerrainSettings::amplitude`` the biome's hills reach
+    pub amplitude: f32,
+    /// how many times ``TerrainSettings::scale`` the biome's hills are wide
+    pub scale: f32,
+    /// the name of the block the stone is covered with
+    pub cover: String,
+    /// how many voxels of ``cover`` lie on the stone
+    pub depth: i32,
+    /// the chance for a tree or a plant on each voxel of open ground, replacing
+    /// the ones in ``TreeSettings``
+    pub tree_chance: f32,
+    pub plant_chance: f32,
+}
+
+/// the biomes a ``TerrainGenerator`` picks from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BiomeSettings {
+    /// the size of the areas of similar climate, in voxels
+    pub climate_scale: f32,
+    /// how far apart on the climate map two biomes blend into each other.
+    /// the larger, the wider and smoother their borders
+    pub blend: f32,
+    pub biomes: Vec<BiomeParams>,
+}
+impl Default for BiomeSettings {
+    fn default() -> Self {
+        let biome = |biome, temperature, humidity, height, amplitude, scale, cover: &str, depth, tree_chance, plant_chance| BiomeParams {
+            biome,
+            temperature,
+            humidity,
+            height,
+            amplitude,
+            scale,
+            cover: cover.to_string(),
+            depth,
+            tree_chance,
+            plant_chance,
+        };
+        Self {
+            climate_scale: 768.0,
+            blend: 0.08,
+            biomes: vec![
+                biome(Biome::Plains, 0.0, 0.0, 2, 0.25, 1.0, "ground", 4, 0.002, 0.12),
+                biome(Biome::Desert, 0.25, -0.25, 4, 0.3, 0.5, "ground", 16, 0.0, 0.004),
+                biome(Biome::Highlands, -0.25, -0.15, 30, 1.2, 0.5, "stone", 0, 0.0, 0.0),
+                biome(Biome::Forest, -0.1, 0.2, 6, 0.5, 1.0, "ground", 4, 0.03, 0.1),
+                biome(Biome::Wetlands, 0.2, 0.25, -3, 0.08, 1.0, "ground", 3, 0.004, 0.3),
+            ],
+        }
+    }
+}
+
+/// how much each biome shapes a column, see ``BiomeMap::weights_at``
+#[derive(Debug, Clone, Copy)]
+pub struct BiomeWeights {
+    weights: [(usize, f32); MAX_BIOMES],
+    len: usize,
+}
+impl Deref for BiomeWeights {
+    type Target = [(usize, f32)];
+    fn deref(&self) -> &Self::Target {
+        &self.weights[..self.len]
+    }
+}
+
+/// which biome lies where, from a temperature and a humidity noise map.
+/// every column belongs to the biome closest to its climate, and columns near the border
+/// of two biomes take their shape from both, so the terrain doesnt jump between them
+pub struct BiomeMap {
+    seed: u64,
+    settings: BiomeSettings,
+    /// the cover block of each biome in ``settings``
+    covers: Vec<BlockID>,
+}
+impl BiomeMap {
+    pub fn new(seed: u64, settings: &BiomeSettings, registry: &BlockRegistry) -> io::Result<Self> {
+        if settings.biomes.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "a biome map needs at least one biome"));
+        }
+        if settings.biomes.len() > MAX_BIOMES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("a biome map can have at most {MAX_BIOMES} biomes, not {}", settings.biomes.len()),
+            ));
+        }
+        let covers = settings.biomes.iter()
+            .map(|params| registry.by_name(&params.cover).ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} covered with block {:?}, which isnt registered", params.biome, params.cover),
+            )))
+            .collect::<io::Result<_>>()?;
+        Ok(Self {
+            seed,
+            settings: settings.clone(),
+            covers,
+        })
+    }
+    pub fn settings(&self) -> &BiomeSettings {
+        &self.settings
+    }
+    /// the temperature and humidity of the column at ``x``, ``z``, each from -1 to 1
+    pub fn climate_at(&self, x: i32, z: i32) -> Vec2 {
+        let pos = Vec2::new(x as f32, z as f32) / self.settings.climate_scale;
+        Vec2::new(
+            fbm(hash(self.seed, 6, 0), pos, 3, 0.5, 2.0),
+            fbm(hash(self.seed, 7, 0), pos, 3, 0.5, 2.0),
+        )
+    }
+    /// how much each biome shapes the column at ``x``, ``z``, as indices into
+    /// ``BiomeSettings::biomes``. the weights add up to 1, and the biome the column belongs to comes first
+    pub fn weights_at(&self, x: i32, z: i32) -> BiomeWeights {
+        let climate = self.climate_at(x, z);
+        let mut distances = [0.0; MAX_BIOMES];
+        for (distance, params) in distances.iter_mut().zip(&self.settings.biomes) {
+            *distance = climate.distance(Vec2::new(params.temperature, params.humidity));
+        }
+        let distances = &distances[..self.settings.biomes.len()];
+        let (closest, nearest) = distances.iter().copied().enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((0, 0.0));
+        let blend = self.settings.blend.max(f32::EPSILON);
+        let mut weights = BiomeWeights { weights: [(closest, 1.0); MAX_BIOMES], len: 1 };
+        for (index, distance) in distances.iter().enumerate() {
+            // reaches 1 where the two biomes meet, like the weight of the closest one
+            let weight = (1.0 - (distance - nearest) / blend).max(0.0).powi(2);
+            if index != closest && weight > 0.0 {
+                weights.weights[weights.len] = (index, weight);
+                weights.len += 1;
+            }
+        }
+        let total: f32 = weights.iter().map(|(_, weight)| weight).sum();
+        for (_, weight) in &mut weights.weights[..weights.len] {
+            *weight /= total;
+        }
+        weights
+    }
+    /// the biome the column at ``x``, ``z`` belongs to, as an index into ``BiomeSettings::biomes``
+    pub fn index_at(&self, x: i32, z: i32) -> usize {
+        self.weights_at(x, z)[0].0
+    }
+    /// the biome at the world position ``pos``. biomes go all the way up and down
+    pub fn biome_at(&self, pos: IVec3) -> Biome {
+        self.settings.biomes[self.index_at(pos.x, pos.z)].biome
+    }
+    pub fn params(&self, index: usize) -> &BiomeParams {
+        &self.settings.biomes[index]
+    }
+    /// the block the biome at ``index`` covers the stone with
+    pub fn cover(&self, index: usize) -> BlockID {
+        self.covers[index]
+    }
+}
+
+/// the biomes of the world, for gameplay, see ``report_player_biome``.
+/// empty if the world's generator doesnt have biomes
+#[derive(Resource, Clone, Default)]
+pub struct WorldBiomes(pub Option<Arc<BiomeMap>>);
+impl WorldBiomes {
+    /// the biomes of ``WorldSettings::generator``
+    pub fn new(settings: &WorldSettings, registry: &BlockRegistry) -> Self {
+        let biomes = settings.generator.terrain().and_then(|terrain| terrain.biomes.as_ref());
+        // a generator that cant be built has already been reported
+        Self(biomes.and_then(|biomes| BiomeMap::new(settings.seed, biomes, registry).ok()).map(Arc::new))
+    }
+    /// the biome at the world position ``pos``, if the world has biomes
+    pub fn biome_at(&self, pos: IVec3) -> Option<Biome> {
+        self.0.as_ref().map(|map| map.biome_at(pos))
+    }
+}
+
+/// logs the biome the player walks into, if the world has biomes
+pub fn report_player_biome(
+    biomes: Res<WorldBiomes>,
+    player: Query<&Transform, With<Player>>,
+    mut current: Local<Option<Biome>>,
+) {
+    let Some(transform) = player.iter().next() else { return; };
+    let biome = biomes.biome_at(transform.translation.floor().as_ivec3());
+    if biome == *current { return; }
+    if let Some(biome) = biome {
+        info!("the player walked into the {biome:?}");
+    }
+    *current = biome;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn biome_map() -> BiomeMap {
+        BiomeMap::new(3, &BiomeSettings::default(), &BlockRegistry::default()).unwrap()
+    }
+
+    /// the weight of every biome in the column at ``x``, ``z``, by index
+    fn all_weights(map: &BiomeMap, x: i32, z: i32) -> [f32; MAX_BIOMES] {
+        let mut all = [0.0; MAX_BIOMES];
+        for &(index, weight) in map.weights_at(x, z).iter() {
+            all[index] += weight;
+        }
+        all
+    }
+
+    #[test]
+    fn weights_add_up_to_one_and_favour_the_biome_of_the_column() {
+        let map = biome_map();
+        for i in 0..2000 {
+            let (x, z) = (i * 97 - 50_000, i * 31 - 20_000);
+            let weights = map.weights_at(x, z);
+            let total: f32 = weights.iter().map(|(_, weight)| weight).sum();
+            assert!((total - 1.0).abs() < 1e-5, "the weights at {x}, {z} add up to {total}");
+            assert!(weights.iter().all(|(_, weight)| *weight > 0.0 && *weight <= weights[0].1), "{weights:?} at {x}, {z}");
+        }
+    }
+
+    #[test]
+    fn weights_change_smoothly_across_biome_borders() {
+        let map = biome_map();
+        let mut borders = 0;
+        for x in -20_000..20_000 {
+            if map.index_at(x, 0) != map.index_at(x + 1, 0) {
+                borders += 1;
+            }
+            let (here, next) = (all_weights(&map, x, 0), all_weights(&map, x + 1, 0));
+            let change: f32 = here.iter().zip(&next).map(|(a, b)| (a - b).abs()).sum();
+            // a hard border would move all of the weight from one biome to another, a change of 2
+            assert!(change < 0.25, "the weights jump by {change} between x {x} and {}", x + 1);
+        }
+        assert!(borders > 0);
+    }
+
+    #[test]
+    fn too_many_biomes_are_refused() {
+        let mut settings = BiomeSettings::default();
+        let plains = settings.biomes[0].clone();
+        settings.biomes.resize(MAX_BIOMES + 1, plains);
+        assert!(BiomeMap::new(3, &settings, &BlockRegistry::default()).is_err());
+        settings.biomes.truncate(MAX_BIOMES);
+        assert!(BiomeMap::new(3, &settings, &BlockRegistry::default()).is_ok());
+    }
+}
@@ -0,0 +1,392 @@
+use std::{collections::HashMap, fmt, fs, sync::Arc};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::fast_voxels::{
+    blocks::{BlockID, GPUBlockID},
+    chunk_manager::{ChunkManager, DirtyChunks},
+    chunk_store::SaveStore,
+};
+
+/// what state of matter a block is in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Phase {
+    #[default]
+    Solid,
+    Liquid,
+    Gas,
+}
+
+/// the physical properties of a block. simulation, mining and rendering all read these,
+/// so there is one place to tune how a block behaves.
+/// temperatures are in kelvin, ``None`` means it never happens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlockProperties {
+    pub phase: Phase,
+    /// in kg/m³
+    pub density: f32,
+    /// how long the block takes to mine, 0 breaks instantly
+    pub hardness: f32,
+    /// how readily fire spreads to the block, from 0 to 1
+    pub flammability: f32,
+    pub ignition_temperature: Option<f32>,
+    pub melting_point: Option<f32>,
+    pub boiling_point: Option<f32>,
+    /// in W/(m·K)
+    pub thermal_conductivity: f32,
+    /// in S/m
+    pub electrical_conductivity: f32,
+}
+impl Default for BlockProperties {
+    fn default() -> Self {
+        Self {
+            phase: Phase::Solid,
+            density: 1000.0,
+            hardness: 1.0,
+            flammability: 0.0,
+            ignition_temperature: None,
+            melting_point: None,
+            boiling_point: None,
+            thermal_conductivity: 1.0,
+            electrical_conductivity: 0.0,
+        }
+    }
+}
+
+/// texture paths, relative to the assets folder. ``all`` is used for any face
+/// that doesnt have its own texture
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlockTextures {
+    pub all: Option<String>,
+    pub top: Option<String>,
+    pub bottom: Option<String>,
+    pub side: Option<String>,
+}
+
+/// one entry of the ``BlockRegistry``
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlockDefinition {
+    /// the stable id saves refer to the block by, ids can change between versions
+    pub name: String,
+    pub id: BlockID,
+    /// whether the block is meshed at all
+    pub visible: bool,
+    /// whether blocks behind this one can be seen, so faces next to it arent culled
+    pub transparent: bool,
+    /// whether entities collide with the block
+    pub solid: bool,
+    /// rgba, used when the block has no textures
+    pub color: [f32; 4],
+    pub textures: BlockTextures,
+    pub properties: BlockProperties,
+}
+impl Default for BlockDefinition {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            id: BlockID::AIR,
+            visible: true,
+            transparent: false,
+            solid: true,
+            color: [1.0; 4],
+            textures: BlockTextures::default(),
+            properties: BlockProperties::default(),
+        }
+    }
+}
+impl BlockDefinition {
+    /// whether the block hides the faces of the blocks next to it
+    pub fn is_opaque(&self) -> bool {
+        self.visible && !self.transparent
+    }
+    /// blocks that look the same share a ``GPUBlockID``
+    fn same_look(&self, other: &Self) -> bool {
+        self.transparent == other.transparent
+            && self.color.map(f32::to_bits) == other.color.map(f32::to_bits)
+            && self.textures == other.textures
+    }
+}
+
+/// the layout of a block registry file
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct RegistryFile {
+    blocks: Vec<BlockDefinition>,
+}
+
+#[derive(Debug)]
+pub enum BlockRegistryError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    EmptyName(BlockID),
+    DuplicateId(BlockID),
+    DuplicateName(String),
+    /// more different looking visible blocks than a ``GPUBlockID`` can number
+    TooManyLooks,
+    /// a block that has another id than before, or is gone. loaded chunks hold the old ids,
+    /// so only a restart can change them
+    ChangedId(String),
+}
+impl fmt::Display for BlockRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "couldnt read block registry: {err}"),
+            Self::Parse(err) => write!(f, "invalid block registry: {err}"),
+            Self::EmptyName(id) => write!(f, "block {} has no name", id.0),
+            Self::DuplicateId(id) => write!(f, "block id {} is used more than once", id.0),
+            Self::DuplicateName(name) => write!(f, "block name {name:?} is used more than once"),
+            Self::TooManyLooks => write!(f, "too many different looking blocks for the gpu ids"),
+            Self::ChangedId(name) => write!(f, "block {name:?} was renumbered or removed, which needs a restart"),
+        }
+    }
+}
+impl std::error::Error for BlockRegistryError {}
+
+struct RegistryData {
+    /// indexed by block id
+    blocks: Vec<Option<BlockDefinition>>,
+    names: HashMap<String, BlockID>,
+    /// indexed by block id, ``None`` for invisible and unknown blocks
+    gpu_ids: Vec<Option<GPUBlockID>>,
+    /// returned for ids that arent in the registry
+    unknown: BlockDefinition,
+}
+
+/// every block the world can contain and its properties, loaded from ``BlockRegistry::PATH``.
+/// cloning is cheap, so mesh tasks can take their own copy
+#[derive(Resource, Asset, TypePath, Clone)]
+pub struct BlockRegistry {
+    data: Arc<RegistryData>,
+}
+impl BlockRegistry {
+    /// relative to the assets folder
+    pub const PATH: &str = "blocks.ron";
+    /// ``PATH`` relative to the working directory, for reading it before the asset server runs
+    pub const FILE: &str = "assets/blocks.ron";
+    pub const PLACEHOLDER_NAME: &str = "placeholder";
+
+    pub fn new(definitions: Vec<BlockDefinition>) -> Result<Self, BlockRegistryError> {
+        let len = definitions.iter().map(|block| block.id.0 as usize + 1).max().unwrap_or(0);
+        let mut blocks = vec![None; len];
+        let mut names = HashMap::new();
+        let mut gpu_ids = vec![None; len];
+        let mut looks: Vec<&BlockDefinition> = Vec::new();
+
+        for block in &definitions {
+            if block.name.is_empty() {
+                return Err(BlockRegistryError::EmptyName(block.id));
+            }
+            if names.insert(block.name.clone(), block.id).is_some() {
+                return Err(BlockRegistryError::DuplicateName(block.name.clone()));
+            }
+            if blocks[block.id.0 as usize].is_some() {
+                return Err(BlockRegistryError::DuplicateId(block.id));
+            }
+            blocks[block.id.0 as usize] = Some(block.clone());
+
+            if !block.visible { continue; }
+            let look = match looks.iter().position(|other| other.same_look(block)) {
+                Some(look) => look,
+                None => {
+                    looks.push(block);
+                    looks.len() - 1
+                }
+            };
+            if look >= GPUBlockID::COUNT {
+                return Err(BlockRegistryError::TooManyLooks);
+            }
+            gpu_ids[block.id.0 as usize] = Some(GPUBlockID(look as u8));
+        }
+
+        Ok(Self {
+            data: Arc::new(RegistryData {
+                blocks,
+                names,
+                gpu_ids,
+                unknown: BlockDefinition {
+                    name: "unknown".to_string(),
+                    color: [1.0, 0.0, 1.0, 1.0],
+                    ..default()
+                },
+            }),
+        })
+    }
+    pub fn from_ron(text: &str) -> Result<Self, BlockRegistryError> {
+        let file: RegistryFile = ron::from_str(text).map_err(BlockRegistryError::Parse)?;
+        Self::new(file.blocks)
+    }
+    /// reads the registry from ``path``, falling back to the built in one if the file
+    /// is missing or invalid
+    pub fn load(path: &str) -> Self {
+        fs::read_to_string(path)
+            .map_err(BlockRegistryError::Io)
+            .and_then(|text| Self::from_ron(&text))
+            .unwrap_or_else(|err| {
+                error!("couldnt load the block registry from {path}, using the built in blocks: {err}");
+                Self::default()
+            })
+    }
+    /// fails if a block of this registry has another id in ``other`` or isnt in it.
+    /// ``other`` can add blocks and change everything else about them
+    pub fn check_same_ids(&self, other: &Self) -> Result<(), BlockRegistryError> {
+        match self.iter().find(|block| other.by_name(&block.name) != Some(block.id)) {
+            Some(block) => Err(BlockRegistryError::ChangedId(block.name.clone())),
+            None => Ok(()),
+        }
+    }
+    /// the definition of ``block``. blocks missing from the registry act like a plain solid block
+    #[inline]
+    pub fn get(&self, block: BlockID) -> &BlockDefinition {
+        self.try_get(block).unwrap_or(&self.data.unknown)
+    }
+    pub fn try_get(&self, block: BlockID) -> Option<&BlockDefinition> {
+        self.data.blocks.get(block.0 as usize)?.as_ref()
+    }
+    /// the block that replaces saved blocks the registry doesnt contain anymore
+    pub fn placeholder(&self) -> BlockID {
+        self.by_name(Self::PLACEHOLDER_NAME).unwrap_or(BlockID::PLACEHOLDER)
+    }
+    /// looks up a block by its stable name
+    pub fn by_name(&self, name: &str) -> Option<BlockID> {
+        self.data.names.get(name).copied()
+    }
+    /// the id the renderer uses for ``block``, ``None`` if it isnt visible or registered
+    pub fn gpu_id(&self, block: BlockID) -> Option<GPUBlockID> {
+        self.data.gpu_ids.get(block.0 as usize).copied().flatten()
+    }
+    /// every registered block, in id order
+    pub fn iter(&self) -> impl Iterator<Item = &BlockDefinition> {
+        self.data.blocks.iter().flatten()
+    }
+    /// the physical properties of ``block``
+    #[inline]
+    pub fn properties(&self, block: BlockID) -> &BlockProperties {
+        &self.get(block).properties
+    }
+    #[inline]
+    pub fn is_visible(&self, block: BlockID) -> bool {
+        self.get(block).visible
+    }
+    #[inline]
+    pub fn is_opaque(&self, block: BlockID) -> bool {
+        self.get(block).is_opaque()
+    }
+    /// whether the face of ``block`` that touches ``neighbor`` has to be meshed.
+    /// faces between two of the same transparent block are culled, so water is only
+    /// meshed on its surface
+    #[inline]
+    pub fn face_visible(&self, block: BlockID, neighbor: BlockID) -> bool {
+        block != neighbor && self.is_visible(block) && !self.is_opaque(neighbor)
+    }
+}
+impl Default for BlockRegistry {
+    /// the blocks the game ships with
+    fn default() -> Self {
+        Self::from_ron(include_str!("../../assets/blocks.ron"))
+            .expect("the default block registry is invalid")
+    }
+}
+
+/// loads a ``BlockRegistry`` from a ron file
+#[derive(Default, TypePath)]
+pub struct BlockRegistryLoader;
+
+impl AssetLoader for BlockRegistryLoader {
+    type Asset = BlockRegistry;
+    type Settings = ();
+    type Error = BlockRegistryError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await.map_err(BlockRegistryError::Io)?;
+        let text = String::from_utf8_lossy(&bytes);
+        BlockRegistry::from_ron(&text)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["blocks.ron"]
+    }
+}
+
+/// the handle of the registry asset that ``sync_block_registry`` copies into the resource
+#[derive(Resource)]
+pub struct BlockRegistryHandle(pub Handle<BlockRegistry>);
+
+pub fn load_block_registry(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(BlockRegistryHandle(asset_server.load(BlockRegistry::PATH)));
+}
+
+/// replaces the ``BlockRegistry`` resource whenever the asset is loaded or changed,
+/// and remeshes every chunk since visibility may have changed.
+/// a registry that renumbers or removes blocks is refused, as loaded chunks and the generator use the ids
+pub fn sync_block_registry(
+    mut events: MessageReader<AssetEvent<BlockRegistry>>,
+    handle: Option<Res<BlockRegistryHandle>>,
+    assets: Res<Assets<BlockRegistry>>,
+    mut registry: ResMut<BlockRegistry>,
+    mut store: ResMut<SaveStore>,
+    chunk_manager: Res<ChunkManager>,
+    mut dirty: ResMut<DirtyChunks>,
+) {
+    let Some(handle) = handle else { return; };
+    let changed = events.read().any(|event| event.is_loaded_with_dependencies(&handle.0) || event.is_modified(&handle.0));
+    if !changed { return; }
+    let Some(loaded) = assets.get(&handle.0) else { return; };
+    if let Err(err) = registry.check_same_ids(loaded) {
+        error!("keeping the old block registry: {err}");
+        return;
+    }
+    *registry = loaded.clone();
+    store.0.set_registry(&registry);
+    for pos in chunk_manager.map.keys() {
+        dirty.mark(*pos);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(name: &str, id: u16) -> BlockDefinition {
+        BlockDefinition { name: name.to_string(), id: BlockID(id), ..default() }
+    }
+
+    #[test]
+    fn reloads_cant_renumber_blocks() {
+        let registry = BlockRegistry::new(vec![block("air", 0), block("stone", 1)]).unwrap();
+        let added = BlockRegistry::new(vec![block("air", 0), block("stone", 1), block("sand", 2)]).unwrap();
+        assert!(registry.check_same_ids(&added).is_ok());
+        let renumbered = BlockRegistry::new(vec![block("air", 0), block("stone", 2)]).unwrap();
+        assert!(matches!(registry.check_same_ids(&renumbered), Err(BlockRegistryError::ChangedId(name)) if name == "stone"));
+        let removed = BlockRegistry::new(vec![block("air", 0), block("sand", 1)]).unwrap();
+        assert!(registry.check_same_ids(&removed).is_err());
+    }
+
+    #[test]
+    fn looks_fit_the_gpu_ids() {
+        let looking = |count: usize| (0..count).map(|index| BlockDefinition {
+            color: [index as f32 / 32.0, 0.0, 0.0, 1.0],
+            ..block(&format!("block {index}"), index as u16)
+        });
+        let registry = BlockRegistry::new(looking(GPUBlockID::COUNT).collect()).unwrap();
+        assert_eq!(registry.gpu_id(BlockID(15)), Some(GPUBlockID(15)));
+        assert!(matches!(BlockRegistry::new(looking(GPUBlockID::COUNT + 1).collect()), Err(BlockRegistryError::TooManyLooks)));
+        // blocks that look the same share an id, and invisible ones dont take one
+        let same = BlockDefinition { color: [3.0 / 32.0, 0.0, 0.0, 1.0], ..block("same", 16) };
+        let hidden = BlockDefinition { visible: false, ..block("hidden", 17) };
+        let registry = BlockRegistry::new(looking(GPUBlockID::COUNT).chain([same, hidden]).collect()).unwrap();
+        assert_eq!(registry.gpu_id(BlockID(16)), Some(GPUBlockID(3)));
+        assert_eq!(registry.gpu_id(BlockID(17)), None);
+    }
+}
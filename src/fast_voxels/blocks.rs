@@ -1,60 +1,66 @@
-use strum_macros::EnumIter;
+use serde::{Deserialize, Serialize};
 
+/// the numeric id of a block. what an id means is defined by the ``BlockRegistry``,
+/// so new blocks can be added without touching the code.
+/// the constants are the ids of the blocks in the default registry.
+#[repr(transparent)]
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BlockID(pub u16);
+impl BlockID {
+    pub const WATER: Self = Self(0);
+    pub const STEAM: Self = Self(1);
+    pub const GROUND: Self = Self(2);
+    pub const STONE: Self = Self(3);
+    pub const STEEL: Self = Self(4);
+    pub const COPPER: Self = Self(5);
+    pub const COAL: Self = Self(6);
+    pub const FIRE: Self = Self(7);
+    pub const OIL: Self = Self(8);
+    pub const WOOD: Self = Self(9);
+    pub const CLOTH: Self = Self(10);
+    pub const MOLTEN_METAL: Self = Self(11);
+    pub const LEAF: Self = Self(12);
+    pub const PLANT: Self = Self(13);
+    pub const AIR: Self = Self(14);
+    pub const HYDROGEN: Self = Self(15);
+    /// stands in for blocks a save uses that the registry doesnt know about
+    pub const PLACEHOLDER: Self = Self(16);
+}
+
+/// extra per-voxel data on top of the ``BlockID``, so blocks that only differ slightly dont
+/// need ids of their own. what it means depends on the block: fluid level for liquids,
+/// burn timer for fire, growth stage for plants, orientation for wood.
+/// 0 is the default state, which isnt stored at all
+#[repr(transparent)]
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BlockState(pub u8);
+impl BlockState {
+    pub const DEFAULT: Self = Self(0);
+    /// how many heights a liquid surface can have
+    pub const FLUID_LEVELS: u8 = 8;
 
-/// an enum for all the possible blocks.
-#[repr(u8)]
-#[derive(PartialEq, Eq, Clone, Copy, Debug, EnumIter)]
-pub enum BlockID {
-    Water = 0,
-    Steam = 1,
-    Ground = 2,
-    Stone = 3,
-    Steel = 4,
-    Copper = 5,
-    Coal = 6,
-    Fire = 7,
-    Oil = 8,
-    Wood = 9,
-    Cloth = 10,
-    MoltenMetal = 11,
-    Leaf = 12,
-    Plant = 13,
-    Air = 14,
-    Hydrogen = 15,
+    /// a liquid whose surface is ``level`` of ``FLUID_LEVELS`` high.
+    /// liquids store how far their surface is below the top, so the default state is full
+    pub fn fluid(level: u8) -> Self {
+        Self(Self::FLUID_LEVELS - level.clamp(1, Self::FLUID_LEVELS))
+    }
+    /// how high a liquid surface is, from 1 to ``FLUID_LEVELS``
+    pub fn fluid_level(self) -> u8 {
+        Self::FLUID_LEVELS - self.0.min(Self::FLUID_LEVELS - 1)
+    }
 }
-pub const TRANSPARENT_BLOCKS: &[BlockID] = &[
-    BlockID::Water,
-    BlockID::Air,
-    BlockID::Hydrogen,
-    BlockID::Steam,
-];
-pub const INVISIBLE_BLOCKS: &[BlockID] = &[
-    BlockID::Air,
-    BlockID::Hydrogen,
-];
 
 /// a specialised compressed version of the ``BlockID``, which contains fewer block ids and therefore fits in less
 /// bits, which means smaller buffers and better performance.
 /// mainly, it doesnt contain blocks that arent rendered, such as air and hydrogen.
-/// it also doesnt contain blocks that look the same. there are no such examples currently, but in future it is possible that
-/// two different blocks behave differently but look the same.
-#[repr(u8)]
-#[derive(PartialEq, Eq, Clone, Copy, Debug, EnumIter)]
-pub enum GPUBlockID {
-    Water = 0,
-    Steam = 1,
-    Ground = 2,
-    Stone = 3,
-    Steel = 4,
-    Copper = 5,
-    Coal = 6,
-    Fire = 7,
-    Oil = 8,
-    Wood = 9,
-    Cloth = 10,
-    MoltenMetal = 11,
-    Leaf = 12,
-    Plant = 13, // the numbers are just so i can keep track for bitpacking stuff
-    _TBD1 = 14,
-    _TBD2 = 15,
-}
\ No newline at end of file
+/// it also doesnt contain blocks that look the same, they share one gpu id.
+/// the ``BlockRegistry`` hands these out.
+#[repr(transparent)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct GPUBlockID(pub u8);
+impl GPUBlockID {
+    /// how many there can be, so they fit in the 4 bits a ``GreedyQuad`` has for them
+    pub const COUNT: usize = 16;
+}
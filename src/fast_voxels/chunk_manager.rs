@@ -0,0 +1,421 @@
+use std::sync::Arc;
+
+use bevy::{
+    diagnostic::FrameCount,
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task, futures_lite::future},
+};
+
+use crate::{
+    fast_voxels::{
+        base_types::{BlockData, CHUNKSIZE, Chunk, DIRECTION_VECS, VoxelMesh},
+        block_registry::BlockRegistry,
+        blocks::{BlockID, BlockState},
+        chunk_store::{LoadedChunk, SaveStore},
+        memory_budget::{ChunkMemory, LastVisible, MeshEvicted},
+        neighborhood::{ChunkNeighborhood, MissingNeighbor},
+        world_gen::WorldGen,
+        world_settings::{WorldSettings, chunk_center},
+    },
+    player::camera::Player,
+};
+
+/// marks a chunk whose mesh has to be regenerated
+#[derive(Component)]
+pub struct NeedsMeshUpdate;
+
+/// marks a chunk whose blocks were changed since it was loaded,
+/// so it has to be saved when it is unloaded
+#[derive(Component)]
+pub struct Modified;
+
+/// counts how often the inputs of a chunk's mesh have changed, meaning the chunk
+/// itself or a neighbour it shares a face with.
+/// mesh tasks are tagged with the version they meshed, so outdated results can be thrown away.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChunkVersion(pub u64);
+
+/// the level of detail a chunk was last meshed with, see ``WorldSettings::lod``
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkLod(pub u32);
+
+/// a running mesh task. replacing or removing this component drops the task, which cancels it
+#[derive(Component)]
+pub struct GenMesh {
+    task: Task<(Entity, VoxelMesh)>,
+    version: ChunkVersion,
+}
+
+/// sets the block and its state at a world position. the chunk and any neighbour sharing
+/// the changed voxel's face get remeshed.
+#[derive(Message, Debug, Clone, Copy)]
+pub struct SetBlock {
+    pub pos: IVec3,
+    pub block: BlockID,
+    pub state: BlockState,
+}
+
+/// chunks that have to be remeshed this frame. collecting them in a set
+/// means a chunk touched by several loads or edits is only remeshed once.
+#[derive(Resource, Default)]
+pub struct DirtyChunks(pub HashSet<IVec3>);
+impl DirtyChunks {
+    pub fn mark(&mut self, chunk_pos: IVec3) {
+        self.0.insert(chunk_pos);
+    }
+    /// marks the 6 chunks that share a face with ``chunk_pos``
+    pub fn mark_neighbors(&mut self, chunk_pos: IVec3) {
+        for direction in DIRECTION_VECS {
+            self.mark(chunk_pos + direction);
+        }
+    }
+    /// marks the chunks whose meshes can see the voxel at ``local_pos`` of ``chunk_pos``,
+    /// which is the chunk itself plus one neighbour for every border the voxel lies on.
+    pub fn mark_voxel(&mut self, chunk_pos: IVec3, local_pos: IVec3) {
+        self.mark(chunk_pos);
+        for direction in DIRECTION_VECS {
+            let neighbor = local_pos + direction;
+            if neighbor.min_element() < 0 || neighbor.max_element() >= CHUNKSIZE as i32 {
+                self.mark(chunk_pos + direction);
+            }
+        }
+    }
+}
+
+/// chunks being loaded or generated on the ``AsyncComputeTaskPool``, which are added once they are done
+#[derive(Resource, Default)]
+pub struct GeneratingChunks(pub HashMap<IVec3, Task<LoadedChunk>>);
+
+/// world storage. maps chunk positions to the block data of every loaded chunk
+#[derive(Resource, Clone, Default)]
+pub struct ChunkManager {
+    pub map: HashMap<IVec3, BlockData>,
+    pub entities: HashMap<IVec3, Entity>,
+}
+impl ChunkManager {
+    /// gets the block at a world position, or air if the chunk isnt loaded
+    pub fn get_block(&self, pos: IVec3) -> BlockID {
+        let chunk_pos = pos.div_euclid(IVec3::splat(CHUNKSIZE as i32));
+        let local_pos = pos.rem_euclid(IVec3::splat(CHUNKSIZE as i32));
+
+        if let Some(data) = self.map.get(&chunk_pos) {
+            return data.get(local_pos);
+        }
+        BlockID::AIR
+    }
+    /// collects the chunk at ``pos`` and its 26 neighbours for meshing
+    pub fn neighborhood(&self, pos: IVec3, policy: MissingNeighbor) -> Option<ChunkNeighborhood> {
+        ChunkNeighborhood::from_fn(pos, policy, |chunk_pos| self.map.get(&chunk_pos).cloned())
+    }
+    pub fn add_chunk(
+        &mut self,
+        commands: &mut Commands,
+        dirty: &mut DirtyChunks,
+        chunk: Chunk,
+    ) -> Entity {
+        let key = chunk.pos;
+        self.map.insert(key, Arc::clone(&chunk.data));
+        // a chunk that was generated again replaces the loaded one
+        let entity = match self.entities.get(&key) {
+            Some(&entity) => commands.entity(entity).insert(chunk).id(),
+            None => commands.spawn((
+                chunk,
+                ChunkVersion::default(),
+                ChunkMemory::default(),
+                Transform::from_translation(key.as_vec3() * CHUNKSIZE as f32),
+            )).id(),
+        };
+        self.entities.insert(key, entity);
+
+        dirty.mark(key);
+        dirty.mark_neighbors(key);
+        entity
+    }
+    pub fn remove_chunk(
+        &mut self,
+        commands: &mut Commands,
+        dirty: &mut DirtyChunks,
+        pos: IVec3,
+    ) {
+        self.map.remove(&pos);
+        if let Some(entity) = self.entities.remove(&pos) {
+            commands.entity(entity).despawn();
+        }
+        dirty.mark_neighbors(pos);
+    }
+}
+
+/// loads the chunks around the player and marks them as visible. chunks are read from the
+/// ``SaveStore``, or generated if they were never saved, in the background, see ``add_generated_chunks``.
+/// visible chunks whose level of detail changed as the player moved are remeshed.
+/// chunks outside of the render distance stay loaded until the memory budget evicts them
+pub fn manage_chunks(
+    mut commands: Commands,
+    chunk_manager: Res<ChunkManager>,
+    mut generating: ResMut<GeneratingChunks>,
+    mut dirty: ResMut<DirtyChunks>,
+    mut store: ResMut<SaveStore>,
+    world_gen: Res<WorldGen>,
+    settings: Res<WorldSettings>,
+    frame: Res<FrameCount>,
+    mut visible: Query<(&mut LastVisible, Has<MeshEvicted>, Option<&ChunkLod>)>,
+    player: Query<&Transform, With<Player>>
+) {
+    for transform in player {
+        let center: IVec3 = chunk_center(transform.translation);
+        let range: IVec3 = settings.range();
+        let start: IVec3 = center - range;
+        let end: IVec3 = center + range;
+
+        for x in start.x..=end.x {
+            for y in start.y..=end.y {
+                for z in start.z..=end.z {
+                    let index: IVec3 = IVec3::new(x,y,z);
+                    if let Some(&entity) = chunk_manager.entities.get(&index) {
+                        if let Ok((mut last_visible, mesh_evicted, lod)) = visible.get_mut(entity) {
+                            last_visible.0 = frame.0;
+                            if mesh_evicted {
+                                commands.entity(entity).remove::<MeshEvicted>();
+                                dirty.mark(index);
+                            }
+                            if lod.is_some_and(|lod| lod.0 != settings.lod(center, index)) {
+                                dirty.mark(index);
+                            }
+                        }
+                        continue;
+                    }
+                    if generating.0.contains_key(&index) { continue; }
+
+                    let task = store.0.start_load(index, Arc::clone(&world_gen.0));
+                    generating.0.insert(index, task);
+                }
+            }
+        }
+    }
+}
+
+pub fn apply_block_edits(
+    mut edits: MessageReader<SetBlock>,
+    mut chunk_manager: ResMut<ChunkManager>,
+    mut dirty: ResMut<DirtyChunks>,
+    mut chunks: Query<&mut Chunk>,
+    mut commands: Commands,
+) {
+    for edit in edits.read() {
+        let chunk_pos = edit.pos.div_euclid(IVec3::splat(CHUNKSIZE as i32));
+        let local_pos = edit.pos.rem_euclid(IVec3::splat(CHUNKSIZE as i32));
+        let Some(data) = chunk_manager.map.get_mut(&chunk_pos) else { continue; };
+
+        if data.get(local_pos) == edit.block && data.state(local_pos) == edit.state { continue; }
+        Arc::make_mut(data).set_with_state(local_pos, edit.block, edit.state);
+
+        let data = Arc::clone(data);
+        if let Some(&entity) = chunk_manager.entities.get(&chunk_pos)
+            && let Ok(mut chunk) = chunks.get_mut(entity)
+        {
+            chunk.data = data;
+            commands.entity(entity).insert(Modified);
+        }
+        dirty.mark_voxel(chunk_pos, local_pos);
+    }
+}
+
+/// adds the chunks whose loading or generation finished
+pub fn add_generated_chunks(
+    mut commands: Commands,
+    mut chunk_manager: ResMut<ChunkManager>,
+    mut generating: ResMut<GeneratingChunks>,
+    mut dirty: ResMut<DirtyChunks>,
+    mut store: ResMut<SaveStore>,
+    frame: Res<FrameCount>,
+) {
+    let mut done = Vec::new();
+    for (pos, task) in &mut generating.0 {
+        if let Some(loaded) = future::block_on(future::poll_once(task)) {
+            done.push((*pos, loaded));
+        }
+    }
+    for (pos, loaded) in done {
+        generating.0.remove(&pos);
+        if let LoadedChunk::Damaged(_, err) = &loaded {
+            store.0.damaged(pos, err);
+        }
+        let data = loaded.data();
+        let entity = chunk_manager.add_chunk(&mut commands, &mut dirty, Chunk {data, pos});
+        commands.entity(entity).insert(LastVisible(frame.0));
+    }
+}
+
+/// turns the chunks collected in ``DirtyChunks`` into ``NeedsMeshUpdate`` markers,
+/// and bumps their version so meshes that are still being generated count as outdated
+pub fn flush_dirty_chunks(
+    mut commands: Commands,
+    chunk_manager: Res<ChunkManager>,
+    mut dirty: ResMut<DirtyChunks>,
+    mut versions: Query<&mut ChunkVersion>,
+) {
+    for pos in dirty.0.drain() {
+        let Some(&entity) = chunk_manager.entities.get(&pos) else { continue; };
+        if let Ok(mut version) = versions.get_mut(entity) {
+            version.0 += 1;
+        }
+        commands.entity(entity).insert(NeedsMeshUpdate);
+    }
+}
+
+/// starts meshing the chunks marked with ``NeedsMeshUpdate``, with ``WorldSettings::meshing``
+/// and the level of detail for their distance to the player
+pub fn process_chunks(
+    mut commands: Commands,
+    chunk_manager: Res<ChunkManager>,
+    settings: Res<WorldSettings>,
+    registry: Res<BlockRegistry>,
+    dirty_chunks: Query<(Entity, &Chunk, &ChunkVersion, Has<GenMesh>), With<NeedsMeshUpdate>>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let thread_pool = AsyncComputeTaskPool::get();
+    let center = player.iter().next().map(|transform| chunk_center(transform.translation));
+
+    for (entity, chunk, version, meshing) in dirty_chunks.iter() {
+        let Some(neighborhood) = chunk_manager.neighborhood(chunk.pos, settings.missing_neighbors) else {
+            // whatever the running task produces is already outdated
+            if meshing {
+                commands.entity(entity).remove::<GenMesh>();
+            }
+            continue;
+        };
+        let registry = registry.clone();
+        let meshing = settings.meshing;
+        let lod = center.map_or(0, |center| settings.lod(center, chunk.pos));
+        let task = thread_pool.spawn(async move {
+            (entity, VoxelMesh::gen_mesh(&neighborhood, &registry, meshing, lod))
+        });
+
+        // inserting replaces and drops any task that was still meshing older data
+        commands.entity(entity)
+            .insert((GenMesh { task, version: *version }, ChunkLod(lod)))
+            .remove::<NeedsMeshUpdate>();
+    }
+}
+
+pub fn poll_mesh_tasks(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut GenMesh)>,
+    versions: Query<&ChunkVersion>,
+) {
+    for (entity, mut task) in &mut tasks {
+        if let Some((target_entity, new_mesh)) = future::block_on(future::poll_once(&mut task.task)) {
+            let current = versions.get(target_entity).ok();
+            if current.is_some_and(|current| *current <= task.version)
+                && let Ok(mut entity_cmds) = commands.get_entity(target_entity)
+            {
+                entity_cmds.insert(new_mesh);
+            }
+
+            commands.entity(entity).remove::<GenMesh>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use bevy::{app::TaskPoolPlugin, ecs::system::RunSystemOnce};
+
+    use super::*;
+    use crate::fast_voxels::chunk_storage::ChunkData;
+
+    /// an app that edits and meshes chunks, without a player, a generator or a save
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(TaskPoolPlugin::default())
+            .init_resource::<ChunkManager>()
+            .init_resource::<DirtyChunks>()
+            .insert_resource(WorldSettings::default())
+            .insert_resource(BlockRegistry::default())
+            .add_message::<SetBlock>()
+            .add_systems(Update, (apply_block_edits, flush_dirty_chunks, process_chunks, poll_mesh_tasks).chain());
+        app
+    }
+
+    /// adds a chunk of stone at each of ``positions``
+    fn load(app: &mut App, positions: &[IVec3]) -> Vec<Entity> {
+        let positions = positions.to_vec();
+        app.world_mut()
+            .run_system_once(move |mut commands: Commands, mut chunk_manager: ResMut<ChunkManager>, mut dirty: ResMut<DirtyChunks>| {
+                positions.iter()
+                    .map(|pos| {
+                        let chunk = Chunk { data: BlockData::new(ChunkData::filled(BlockID::STONE)), pos: *pos };
+                        chunk_manager.add_chunk(&mut commands, &mut dirty, chunk)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap()
+    }
+
+    /// updates the app until every chunk that has to be meshed is
+    fn mesh_all(app: &mut App) {
+        for _ in 0..10_000 {
+            app.update();
+            let world = app.world_mut();
+            let mut meshing = world.query_filtered::<(), Or<(With<GenMesh>, With<NeedsMeshUpdate>)>>();
+            if meshing.iter(world).next().is_none() { return; }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("the chunks were never meshed");
+    }
+
+    fn version(app: &App, entity: Entity) -> ChunkVersion {
+        *app.world().get::<ChunkVersion>(entity).unwrap()
+    }
+
+    #[test]
+    fn edits_on_a_border_remesh_the_chunks_on_both_sides() {
+        let mut app = app();
+        let chunks = load(&mut app, &[IVec3::ZERO, IVec3::X, IVec3::Y]);
+        mesh_all(&mut app);
+        let before = chunks.iter().map(|entity| version(&app, *entity)).collect::<Vec<_>>();
+        for entity in &chunks {
+            app.world_mut().entity_mut(*entity).remove::<VoxelMesh>();
+        }
+        // on the +x border of the chunk at the origin, so the chunk next to it sees it too
+        let pos = IVec3::new(CHUNKSIZE as i32 - 1, 5, 5);
+        app.world_mut().write_message(SetBlock { pos, block: BlockID::AIR, state: BlockState::DEFAULT });
+        mesh_all(&mut app);
+
+        let world = app.world();
+        let [edited, beside, above] = chunks[..] else { unreachable!() };
+        assert!(world.get::<Modified>(edited).is_some());
+        assert!(world.get::<Modified>(beside).is_none());
+        assert_eq!(world.resource::<ChunkManager>().get_block(pos), BlockID::AIR);
+        assert_eq!(version(&app, edited).0, before[0].0 + 1);
+        assert_eq!(version(&app, beside).0, before[1].0 + 1);
+        assert_eq!(version(&app, above), before[2]);
+        assert!(world.get::<VoxelMesh>(edited).is_some());
+        assert!(world.get::<VoxelMesh>(beside).is_some());
+        assert!(world.get::<VoxelMesh>(above).is_none());
+    }
+
+    #[test]
+    fn meshes_of_outdated_versions_are_dropped() {
+        let mut app = app();
+        let entity = load(&mut app, &[IVec3::ZERO])[0];
+        mesh_all(&mut app);
+        app.world_mut().entity_mut(entity).remove::<VoxelMesh>();
+        let current = version(&app, entity);
+        let task = |version| GenMesh {
+            task: AsyncComputeTaskPool::get().spawn(async move { (entity, VoxelMesh::new(IVec3::ZERO)) }),
+            version,
+        };
+
+        app.world_mut().entity_mut(entity).insert(task(ChunkVersion(current.0 - 1)));
+        mesh_all(&mut app);
+        assert!(app.world().get::<VoxelMesh>(entity).is_none());
+
+        app.world_mut().entity_mut(entity).insert(task(current));
+        mesh_all(&mut app);
+        assert!(app.world().get::<VoxelMesh>(entity).is_some());
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use bevy::{app::{App, Last, Plugin, Startup, Update}, asset::AssetApp, diagnostic::RegisterDiagnostic, ecs::schedule::IntoScheduleConfigs, log::error};
+
+use crate::fast_voxels::{
+    block_registry::{BlockRegistry, BlockRegistryLoader, load_block_registry, sync_block_registry},
+    chunk_manager::{ChunkManager, DirtyChunks, GeneratingChunks, SetBlock, add_generated_chunks, apply_block_edits, flush_dirty_chunks, manage_chunks, poll_mesh_tasks, process_chunks},
+    biomes::{WorldBiomes, report_player_biome},
+    chunk_store::{Autosave, SaveStatus, SaveStore, autosave, save_on_exit},
+    memory_budget::{self, MemoryBudget, account_chunk_memory, enforce_memory_budget},
+    region_store::RegionStore,
+    structures::{PlaceStructure, StructureLoader, StructureTemplate, place_structures},
+    world_gen::{FlatGenerator, WorldGen},
+    world_settings::{WorldSettings, apply_generation_settings, apply_world_settings},
+};
+
+/// loads, edits, meshes and evicts chunks around the player.
+/// the ``WorldSettings`` are read from ``WorldSettings::PATH`` unless the app inserts its own,
+/// new chunks come from ``WorldSettings::generator`` unless it inserts a ``WorldGen``,
+/// and ``WorldBiomes`` tells which biome lies where, if the generator has biomes, and which one the player is in.
+/// chunks are saved to region files in ``WorldSettings::save_dir`` unless it inserts a ``SaveStore``.
+/// the ``BlockRegistry`` is read from ``BlockRegistry::FILE`` unless the app inserts its own, so the generator
+/// and the save use the same blocks as the game, and the asset at ``BlockRegistry::PATH`` reloads it.
+/// ``StructureTemplate``s load as assets, and ``PlaceStructure`` places them into the world
+pub struct ChunkPlugin;
+
+impl Plugin for ChunkPlugin {
+    fn build(&self, app: &mut App) {
+        for diagnostic in memory_budget::diagnostics() {
+            app.register_diagnostic(diagnostic);
+        }
+        if !app.world().contains_resource::<WorldSettings>() {
+            app.insert_resource(WorldSettings::load(WorldSettings::PATH));
+        }
+        if !app.world().contains_resource::<BlockRegistry>() {
+            app.insert_resource(BlockRegistry::load(BlockRegistry::FILE));
+        }
+        if !app.world().contains_resource::<WorldGen>() {
+            let settings = app.world().resource::<WorldSettings>();
+            let generator = settings.generator.create(settings, app.world().resource::<BlockRegistry>())
+                .unwrap_or_else(|err| {
+                    error!("couldnt create the {:?} world generator, using flat terrain: {err}", settings.generator);
+                    Arc::new(FlatGenerator::default())
+                });
+            app.insert_resource(WorldGen(generator));
+        }
+        if !app.world().contains_resource::<WorldBiomes>() {
+            let biomes = WorldBiomes::new(app.world().resource::<WorldSettings>(), app.world().resource::<BlockRegistry>());
+            app.insert_resource(biomes);
+        }
+        if !app.world().contains_resource::<SaveStore>() {
+            let settings = app.world().resource::<WorldSettings>();
+            let save_dir = settings.save_dir.clone();
+            let generator = Arc::clone(&app.world().resource::<WorldGen>().0);
+            let store = match RegionStore::open(&save_dir, app.world().resource::<BlockRegistry>(), settings, generator) {
+                Ok(store) => SaveStore(Box::new(store)),
+                Err(err) => {
+                    error!("couldnt open the world in {save_dir}, nothing will be saved: {err}");
+                    SaveStore::default()
+                }
+            };
+            app.insert_resource(store);
+        }
+        app
+            .init_asset::<BlockRegistry>()
+            .register_asset_loader(BlockRegistryLoader)
+            .init_asset::<StructureTemplate>()
+            .register_asset_loader(StructureLoader)
+            .init_resource::<ChunkManager>()
+            .init_resource::<GeneratingChunks>()
+            .init_resource::<DirtyChunks>()
+            .init_resource::<MemoryBudget>()
+            .init_resource::<Autosave>()
+            .add_message::<SetBlock>()
+            .add_message::<SaveStatus>()
+            .add_message::<PlaceStructure>()
+            .add_systems(Startup, load_block_registry)
+            .add_systems(Update, (
+                sync_block_registry,
+                apply_world_settings,
+                apply_generation_settings,
+                manage_chunks,
+                add_generated_chunks,
+                apply_block_edits,
+                place_structures,
+                account_chunk_memory,
+                enforce_memory_budget,
+                autosave,
+                flush_dirty_chunks,
+                process_chunks,
+                poll_mesh_tasks,
+            ).chain())
+            .add_systems(Update, report_player_biome)
+            .add_systems(Last, save_on_exit);
+    }
+}
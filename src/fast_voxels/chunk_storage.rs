@@ -0,0 +1,544 @@
+use std::mem::size_of;
+
+use bevy::math::IVec3;
+
+use crate::fast_voxels::{base_types::CHUNKSIZE, blocks::{BlockID, BlockState}};
+
+/// the number of voxels in a chunk
+pub const CHUNK_VOLUME: usize = CHUNKSIZE * CHUNKSIZE * CHUNKSIZE;
+
+/// every block of a chunk, indexed by x, then y, then z
+pub type BlockArray = [[[BlockID; CHUNKSIZE]; CHUNKSIZE]; CHUNKSIZE];
+
+/// everything stored per voxel of a chunk: the block ids, and the states of the voxels
+/// that have one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkData {
+    pub blocks: ChunkStorage,
+    pub states: StateStorage,
+}
+impl ChunkData {
+    pub fn new(blocks: ChunkStorage) -> Self {
+        Self {
+            blocks,
+            states: StateStorage::Empty,
+        }
+    }
+    /// a chunk filled with ``block`` in its default state
+    pub fn filled(block: BlockID) -> Self {
+        Self::new(ChunkStorage::Uniform(block))
+    }
+    #[inline]
+    pub fn get(&self, pos: IVec3) -> BlockID {
+        self.blocks.get(pos)
+    }
+    #[inline]
+    pub fn state(&self, pos: IVec3) -> BlockState {
+        self.states.get(ChunkStorage::index(pos))
+    }
+    /// sets the block at ``pos``, resetting its state
+    pub fn set(&mut self, pos: IVec3, block: BlockID) {
+        self.set_with_state(pos, block, BlockState::DEFAULT);
+    }
+    pub fn set_with_state(&mut self, pos: IVec3, block: BlockID, state: BlockState) {
+        self.blocks.set(pos, block);
+        self.states.set(ChunkStorage::index(pos), state);
+    }
+    /// changes the state of the voxel at ``pos`` and keeps its block
+    pub fn set_state(&mut self, pos: IVec3, state: BlockState) {
+        self.states.set(ChunkStorage::index(pos), state);
+    }
+    /// the block every voxel of the chunk has, if they are all the same and in the default state
+    pub fn uniform(&self) -> Option<BlockID> {
+        if !self.states.is_empty() { return None; }
+        self.blocks.uniform()
+    }
+    pub fn heap_size(&self) -> usize {
+        self.blocks.heap_size() + self.states.heap_size()
+    }
+}
+
+/// the block ids of one chunk, stored in whichever form is smallest for its contents.
+/// edits promote and demote between the forms automatically:
+/// - ``Uniform`` when every voxel is the same block, which is most of a flat world
+/// - ``Paletted`` when there are few enough different blocks that indexing a palette
+///   takes fewer bits than a block id
+/// - ``Dense`` otherwise
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkStorage {
+    Uniform(BlockID),
+    Paletted(PalettedStorage),
+    Dense(DenseStorage),
+}
+impl ChunkStorage {
+    /// a palette this wide takes as much space as storing the block ids directly
+    pub const DENSE_BITS: u32 = (size_of::<BlockID>() * 8) as u32;
+
+    /// turns a local position into an index into the flattened chunk
+    #[inline]
+    pub fn index(pos: IVec3) -> usize {
+        debug_assert!(
+            pos.min_element() >= 0 && pos.max_element() < CHUNKSIZE as i32,
+            "{pos} is outside of the chunk",
+        );
+        (pos.x as usize * CHUNKSIZE + pos.y as usize) * CHUNKSIZE + pos.z as usize
+    }
+    /// turns an index into the flattened chunk back into a local position
+    #[inline]
+    pub fn position(index: usize) -> IVec3 {
+        IVec3::new(
+            (index / (CHUNKSIZE * CHUNKSIZE)) as i32,
+            (index / CHUNKSIZE % CHUNKSIZE) as i32,
+            (index % CHUNKSIZE) as i32,
+        )
+    }
+    /// picks the smallest storage for ``blocks``
+    pub fn from_blocks(blocks: &BlockArray) -> Self {
+        DenseStorage::from_blocks(blocks).compact()
+    }
+    /// the block at the local position ``pos``
+    #[inline]
+    pub fn get(&self, pos: IVec3) -> BlockID {
+        match self {
+            Self::Uniform(block) => *block,
+            Self::Paletted(paletted) => paletted.get(Self::index(pos)),
+            Self::Dense(dense) => dense.get(Self::index(pos)),
+        }
+    }
+    /// sets the block at the local position ``pos``, changing the storage form if needed
+    pub fn set(&mut self, pos: IVec3, block: BlockID) {
+        let index = Self::index(pos);
+        match self {
+            Self::Uniform(current) => {
+                if *current == block { return; }
+                let mut paletted = PalettedStorage::filled(*current);
+                paletted.set(index, block);
+                *self = Self::Paletted(paletted);
+            }
+            Self::Paletted(paletted) => {
+                paletted.set(index, block);
+                if paletted.bits() >= Self::DENSE_BITS {
+                    *self = Self::Dense(DenseStorage::from_paletted(paletted));
+                }
+            }
+            Self::Dense(dense) => dense.set(index, block),
+        }
+        self.demote();
+    }
+    /// the block every voxel of the chunk has, if they are all the same
+    pub fn uniform(&self) -> Option<BlockID> {
+        match self {
+            Self::Uniform(block) => Some(*block),
+            _ => None,
+        }
+    }
+    /// copies every block out into a plain array
+    pub fn to_blocks(&self) -> Box<BlockArray> {
+        let mut blocks = Box::new([[[BlockID::AIR; CHUNKSIZE]; CHUNKSIZE]; CHUNKSIZE]);
+        for (index, block) in blocks.as_flattened_mut().as_flattened_mut().iter_mut().enumerate() {
+            *block = match self {
+                Self::Uniform(block) => *block,
+                Self::Paletted(paletted) => paletted.get(index),
+                Self::Dense(dense) => dense.get(index),
+            };
+        }
+        blocks
+    }
+    /// the bytes used by the blocks outside of the enum itself
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Self::Uniform(_) => 0,
+            Self::Paletted(paletted) => paletted.heap_size(),
+            Self::Dense(dense) => dense.heap_size(),
+        }
+    }
+    /// switches to a smaller storage form once the contents allow it.
+    /// narrower forms are only picked once the contents fit them with room to spare,
+    /// so a chunk right at the limit doesnt flip between forms on every edit
+    fn demote(&mut self) {
+        match self {
+            Self::Uniform(_) => {}
+            Self::Paletted(paletted) => {
+                if let Some(block) = paletted.uniform() {
+                    *self = Self::Uniform(block);
+                } else if paletted.bits() > 1 && paletted.live_entries() <= (1 << (paletted.bits() / 2)) / 2 {
+                    *paletted = paletted.compacted();
+                }
+            }
+            Self::Dense(dense) => {
+                if dense.distinct <= (1 << (Self::DENSE_BITS / 2)) / 2 {
+                    *self = dense.compact();
+                }
+            }
+        }
+    }
+}
+
+/// a palette of blocks, with each voxel stored as an index into it.
+/// indices are 1, 2, 4, 8 or 16 bits wide, and get wider when a block is added
+/// to a full palette. an index never spans two words
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PalettedStorage {
+    palette: Vec<BlockID>,
+    /// how many voxels use each palette entry. entries with a count of 0 get reused
+    counts: Vec<u16>,
+    bits: u32,
+    /// the indices of ``64 / bits`` voxels per word, the lowest bits are the first voxel
+    words: Vec<u64>,
+}
+impl PalettedStorage {
+    pub const MAX_BITS: u32 = 16;
+
+    /// a chunk filled with ``block``
+    pub fn filled(block: BlockID) -> Self {
+        Self {
+            palette: vec![block],
+            counts: vec![CHUNK_VOLUME as u16],
+            bits: 1,
+            words: vec![0; CHUNK_VOLUME / 64],
+        }
+    }
+    /// the narrowest index width that can address ``entries`` palette entries
+    pub fn bits_for(entries: usize) -> u32 {
+        match entries {
+            0..=2 => 1,
+            3..=4 => 2,
+            5..=16 => 4,
+            17..=256 => 8,
+            _ => 16,
+        }
+    }
+    pub fn palette(&self) -> &[BlockID] {
+        &self.palette
+    }
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+    /// how many palette entries are used by at least one voxel
+    pub fn live_entries(&self) -> usize {
+        self.counts.iter().filter(|count| **count > 0).count()
+    }
+    #[inline]
+    pub fn palette_index(&self, index: usize) -> usize {
+        let per_word = 64 / self.bits as usize;
+        let shift = (index % per_word) as u32 * self.bits;
+        ((self.words[index / per_word] >> shift) & self.mask()) as usize
+    }
+    /// the palette index of every voxel, in the same order as ``ChunkStorage::index``
+    pub fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        let bits = self.bits;
+        let mask = self.mask();
+        self.words.iter().flat_map(move |word| {
+            (0..64 / bits).map(move |i| ((word >> (i * bits)) & mask) as usize)
+        })
+    }
+    #[inline]
+    pub fn get(&self, index: usize) -> BlockID {
+        self.palette[self.palette_index(index)]
+    }
+    /// sets the voxel at ``index``, widening the indices if the palette is full
+    pub fn set(&mut self, index: usize, block: BlockID) {
+        let old = self.palette_index(index);
+        if self.palette[old] == block { return; }
+
+        let new = self.entry(block);
+        self.counts[old] -= 1;
+        self.counts[new] += 1;
+        self.write(index, new);
+    }
+    /// the block every voxel has, if they are all the same
+    pub fn uniform(&self) -> Option<BlockID> {
+        let index = self.counts.iter().position(|count| *count as usize == CHUNK_VOLUME)?;
+        Some(self.palette[index])
+    }
+    /// the same blocks with unused palette entries removed and the narrowest indices that fit
+    pub fn compacted(&self) -> Self {
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = Vec::new();
+        let mut counts = Vec::new();
+        for (old, (block, count)) in self.palette.iter().zip(&self.counts).enumerate() {
+            if *count == 0 { continue; }
+            remap[old] = palette.len();
+            palette.push(*block);
+            counts.push(*count);
+        }
+        let mut compacted = Self {
+            bits: Self::bits_for(palette.len()),
+            palette,
+            counts,
+            words: Vec::new(),
+        };
+        compacted.words = vec![0; CHUNK_VOLUME * compacted.bits as usize / 64];
+        for (index, old) in self.indices().enumerate() {
+            compacted.write(index, remap[old]);
+        }
+        compacted
+    }
+    pub fn heap_size(&self) -> usize {
+        self.palette.capacity() * size_of::<BlockID>()
+            + self.counts.capacity() * size_of::<u16>()
+            + self.words.capacity() * size_of::<u64>()
+    }
+    fn mask(&self) -> u64 {
+        (1 << self.bits) - 1
+    }
+    /// the palette entry for ``block``, adding it if it isnt in the palette yet
+    fn entry(&mut self, block: BlockID) -> usize {
+        if let Some(entry) = self.palette.iter().position(|entry| *entry == block) {
+            return entry;
+        }
+        if let Some(free) = self.counts.iter().position(|count| *count == 0) {
+            self.palette[free] = block;
+            return free;
+        }
+        if self.palette.len() == 1 << self.bits {
+            self.widen();
+        }
+        self.palette.push(block);
+        self.counts.push(0);
+        self.palette.len() - 1
+    }
+    /// doubles the width of every index
+    fn widen(&mut self) {
+        debug_assert!(self.bits < Self::MAX_BITS, "palette cant hold more than 2^16 blocks");
+        let mut widened = Self {
+            palette: Vec::new(),
+            counts: Vec::new(),
+            bits: self.bits * 2,
+            words: vec![0; CHUNK_VOLUME * self.bits as usize * 2 / 64],
+        };
+        for (index, entry) in self.indices().enumerate() {
+            widened.write(index, entry);
+        }
+        self.bits = widened.bits;
+        self.words = widened.words;
+    }
+    #[inline]
+    fn write(&mut self, index: usize, entry: usize) {
+        let per_word = 64 / self.bits as usize;
+        let shift = (index % per_word) as u32 * self.bits;
+        let mask = self.mask() << shift;
+        let word = &mut self.words[index / per_word];
+        *word = (*word & !mask) | ((entry as u64) << shift);
+    }
+}
+
+/// one block id per voxel, plus how often each block id is used so the
+/// chunk can be demoted again once it gets simpler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DenseStorage {
+    blocks: Box<BlockArray>,
+    /// indexed by block id
+    counts: Vec<u16>,
+    /// how many block ids have a count above 0
+    distinct: usize,
+}
+impl DenseStorage {
+    pub fn from_blocks(blocks: &BlockArray) -> Self {
+        let mut dense = Self {
+            blocks: Box::new(*blocks),
+            counts: Vec::new(),
+            distinct: 0,
+        };
+        for index in 0..CHUNK_VOLUME {
+            dense.add(dense.get(index), 1);
+        }
+        dense
+    }
+    fn from_paletted(paletted: &PalettedStorage) -> Self {
+        let mut blocks = Box::new([[[BlockID::AIR; CHUNKSIZE]; CHUNKSIZE]; CHUNKSIZE]);
+        for (index, block) in blocks.as_flattened_mut().as_flattened_mut().iter_mut().enumerate() {
+            *block = paletted.get(index);
+        }
+        let mut dense = Self {
+            blocks,
+            counts: Vec::new(),
+            distinct: 0,
+        };
+        for (block, count) in paletted.palette.iter().zip(&paletted.counts) {
+            dense.add(*block, *count);
+        }
+        dense
+    }
+    pub fn blocks(&self) -> &BlockArray {
+        &self.blocks
+    }
+    #[inline]
+    pub fn get(&self, index: usize) -> BlockID {
+        self.blocks.as_flattened().as_flattened()[index]
+    }
+    pub fn set(&mut self, index: usize, block: BlockID) {
+        let voxel = &mut self.blocks.as_flattened_mut().as_flattened_mut()[index];
+        let old = *voxel;
+        if old == block { return; }
+        *voxel = block;
+        self.remove(old);
+        self.add(block, 1);
+    }
+    /// the smallest storage that can hold the same blocks
+    pub fn compact(&self) -> ChunkStorage {
+        if self.distinct == 1 {
+            return ChunkStorage::Uniform(self.get(0));
+        }
+        if PalettedStorage::bits_for(self.distinct) >= ChunkStorage::DENSE_BITS {
+            return ChunkStorage::Dense(self.clone());
+        }
+        let mut paletted = PalettedStorage::filled(self.get(0));
+        for index in 0..CHUNK_VOLUME {
+            paletted.set(index, self.get(index));
+        }
+        ChunkStorage::Paletted(paletted.compacted())
+    }
+    pub fn heap_size(&self) -> usize {
+        size_of::<BlockArray>() + self.counts.capacity() * size_of::<u16>()
+    }
+    fn add(&mut self, block: BlockID, count: u16) {
+        if count == 0 { return; }
+        let id = block.0 as usize;
+        if self.counts.len() <= id {
+            self.counts.resize(id + 1, 0);
+        }
+        if self.counts[id] == 0 {
+            self.distinct += 1;
+        }
+        self.counts[id] += count;
+    }
+    fn remove(&mut self, block: BlockID) {
+        let id = block.0 as usize;
+        self.counts[id] -= 1;
+        if self.counts[id] == 0 {
+            self.distinct -= 1;
+        }
+    }
+}
+
+/// the states of a chunk's voxels. most voxels are in the default state, so only the others
+/// are stored, as a sorted list while there are few of them and as an array once that is smaller
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum StateStorage {
+    #[default]
+    Empty,
+    /// voxel indices and their states, sorted by index
+    Sparse(Vec<(u16, BlockState)>),
+    Dense {
+        states: Box<[BlockState; CHUNK_VOLUME]>,
+        /// how many voxels arent in the default state
+        count: usize,
+    },
+}
+impl StateStorage {
+    /// the most voxels a sparse list holds before switching to an array
+    pub const SPARSE_LIMIT: usize = CHUNK_VOLUME / size_of::<(u16, BlockState)>() / 2;
+
+    #[inline]
+    pub fn get(&self, index: usize) -> BlockState {
+        match self {
+            Self::Empty => BlockState::DEFAULT,
+            Self::Sparse(states) => match states.binary_search_by_key(&(index as u16), |(index, _)| *index) {
+                Ok(found) => states[found].1,
+                Err(_) => BlockState::DEFAULT,
+            },
+            Self::Dense { states, .. } => states[index],
+        }
+    }
+    pub fn set(&mut self, index: usize, state: BlockState) {
+        match self {
+            Self::Empty => {
+                if state != BlockState::DEFAULT {
+                    *self = Self::Sparse(vec![(index as u16, state)]);
+                }
+            }
+            Self::Sparse(states) => {
+                match states.binary_search_by_key(&(index as u16), |(index, _)| *index) {
+                    Ok(found) if state == BlockState::DEFAULT => { states.remove(found); }
+                    Ok(found) => states[found].1 = state,
+                    Err(_) if state == BlockState::DEFAULT => {}
+                    Err(at) => states.insert(at, (index as u16, state)),
+                }
+                if states.is_empty() {
+                    *self = Self::Empty;
+                } else if states.len() > Self::SPARSE_LIMIT {
+                    let mut dense = Box::new([BlockState::DEFAULT; CHUNK_VOLUME]);
+                    for (index, state) in states.iter() {
+                        dense[*index as usize] = *state;
+                    }
+                    *self = Self::Dense { states: dense, count: states.len() };
+                }
+            }
+            Self::Dense { states, count } => {
+                let old = std::mem::replace(&mut states[index], state);
+                match (old == BlockState::DEFAULT, state == BlockState::DEFAULT) {
+                    (true, false) => *count += 1,
+                    (false, true) => *count -= 1,
+                    _ => {}
+                }
+                // only switching back well below the limit keeps it from flipping on every edit
+                if *count <= Self::SPARSE_LIMIT / 2 {
+                    *self = Self::Sparse(self.iter().map(|(index, state)| (index as u16, state)).collect());
+                }
+            }
+        }
+    }
+    /// whether every voxel is in the default state
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Self::Empty)
+    }
+    /// every voxel that isnt in the default state, in index order
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (usize, BlockState)> + '_> {
+        match self {
+            Self::Empty => Box::new(std::iter::empty()),
+            Self::Sparse(states) => Box::new(states.iter().map(|(index, state)| (*index as usize, *state))),
+            Self::Dense { states, .. } => Box::new(
+                states.iter().enumerate()
+                    .filter(|(_, state)| **state != BlockState::DEFAULT)
+                    .map(|(index, state)| (index, *state)),
+            ),
+        }
+    }
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Self::Empty => 0,
+            Self::Sparse(states) => states.capacity() * size_of::<(u16, BlockState)>(),
+            Self::Dense { .. } => size_of::<[BlockState; CHUNK_VOLUME]>(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fast_voxels::world_gen::SplitMix;
+
+    /// makes random edits with ``kinds`` different blocks to a ``ChunkStorage`` and to a plain
+    /// array side by side, checking that they always hold the same blocks
+    fn check_edits(seed: u64, kinds: u16, edits: usize) {
+        let mut random = SplitMix(seed);
+        let mut storage = ChunkStorage::Uniform(BlockID::AIR);
+        let mut blocks = Box::new([[[BlockID::AIR; CHUNKSIZE]; CHUNKSIZE]; CHUNKSIZE]);
+        for edit in 0..edits {
+            let pos = ChunkStorage::position((random.next_u64() % CHUNK_VOLUME as u64) as usize);
+            let block = BlockID((random.next_u64() % u64::from(kinds)) as u16);
+            storage.set(pos, block);
+            blocks[pos.x as usize][pos.y as usize][pos.z as usize] = block;
+            assert_eq!(storage.get(pos), block);
+            if edit % 4096 == 0 {
+                assert!(storage.to_blocks() == blocks, "seed {seed}: the blocks differ after {edit} edits");
+            }
+        }
+        assert!(storage.to_blocks() == blocks, "seed {seed}: the blocks differ");
+        assert!(ChunkStorage::from_blocks(&blocks).to_blocks() == blocks, "seed {seed}: the blocks differ once compacted");
+
+        for index in 0..CHUNK_VOLUME {
+            storage.set(ChunkStorage::position(index), BlockID::AIR);
+        }
+        assert_eq!(storage, ChunkStorage::Uniform(BlockID::AIR));
+    }
+
+    #[test]
+    fn paletted_storage_matches_dense() {
+        // from 1 bit palettes up to more blocks than a palette is worth
+        for (seed, kinds) in [(1, 2), (2, 3), (3, 17), (4, 300), (5, 5000)] {
+            check_edits(seed, kinds, 40_000);
+        }
+    }
+}
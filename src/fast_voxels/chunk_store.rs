@@ -0,0 +1,292 @@
+use std::{io, sync::Arc};
+
+use bevy::{
+    platform::collections::HashMap,
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+
+use crate::fast_voxels::{
+    base_types::{BlockData, Chunk},
+    block_registry::BlockRegistry,
+    chunk_manager::Modified,
+    world_gen::WorldGenerator,
+    world_settings::WorldSettings,
+};
+
+/// somewhere chunks can be written to when they are unloaded, and read back
+/// from before a chunk is generated.
+pub trait ChunkStore: Send + Sync {
+    fn load(&mut self, pos: IVec3) -> Option<BlockData>;
+    fn save(&mut self, pos: IVec3, data: &BlockData);
+    /// loads the chunk at ``pos`` on the ``AsyncComputeTaskPool``, or generates it with ``generator``
+    /// if the store doesnt have it, so reading and decoding it doesnt hold up the frame
+    fn start_load(&mut self, pos: IVec3, generator: Arc<dyn WorldGenerator>) -> Task<LoadedChunk> {
+        let saved = self.load(pos);
+        AsyncComputeTaskPool::get().spawn(async move {
+            match saved {
+                Some(data) => LoadedChunk::Saved(data),
+                None => LoadedChunk::Generated(generator.generate(pos)),
+            }
+        })
+    }
+    /// called when a chunk ``start_load`` read turned out to be damaged
+    fn damaged(&mut self, _pos: IVec3, _err: &io::Error) {}
+    /// writes out anything ``save`` buffered, waiting for any background flush first
+    fn flush(&mut self) {}
+    /// starts writing out anything ``save`` buffered without blocking, unless a flush is
+    /// already running. returns how many chunks it is writing
+    fn start_flush(&mut self) -> usize {
+        self.flush();
+        0
+    }
+    /// how far the background flush is, or ``None`` if none is running
+    fn poll_flush(&mut self) -> Option<SaveStatus> {
+        None
+    }
+    /// how many bytes of the chunks ``save`` was given are held in memory until they are written,
+    /// which ``enforce_memory_budget`` counts against the block budget
+    fn unwritten_bytes(&self) -> usize {
+        0
+    }
+    /// called when the ``BlockRegistry`` is replaced
+    fn set_registry(&mut self, _registry: &BlockRegistry) {}
+}
+
+/// what a ``ChunkStore::start_load`` task found
+pub enum LoadedChunk {
+    Saved(BlockData),
+    /// the store doesnt have the chunk, so it was generated
+    Generated(BlockData),
+    /// the saved chunk is damaged, so it was generated again
+    Damaged(BlockData, io::Error),
+}
+impl LoadedChunk {
+    pub fn data(self) -> BlockData {
+        match self {
+            Self::Saved(data) | Self::Generated(data) | Self::Damaged(data, _) => data,
+        }
+    }
+}
+
+/// the store the chunk systems save evicted chunks to
+#[derive(Resource)]
+pub struct SaveStore(pub Box<dyn ChunkStore>);
+impl Default for SaveStore {
+    fn default() -> Self {
+        Self(Box::new(MemoryStore::default()))
+    }
+}
+
+/// keeps saved chunks in memory. chunks dont survive a restart, but edits
+/// survive the chunk being evicted and loaded again. they are never written anywhere,
+/// so they dont count as unwritten
+#[derive(Default)]
+pub struct MemoryStore {
+    pub chunks: HashMap<IVec3, BlockData>,
+    /// how many chunks were saved since the last flush started
+    saved: usize,
+    /// how many chunks the last flush took, until ``poll_flush`` reports it
+    flushed: Option<usize>,
+}
+impl ChunkStore for MemoryStore {
+    fn load(&mut self, pos: IVec3) -> Option<BlockData> {
+        self.chunks.get(&pos).cloned()
+    }
+    fn save(&mut self, pos: IVec3, data: &BlockData) {
+        self.chunks.insert(pos, data.clone());
+        self.saved += 1;
+    }
+    /// there is nothing to write, so the flush is done at once
+    fn start_flush(&mut self) -> usize {
+        let chunks = std::mem::take(&mut self.saved);
+        if chunks > 0 {
+            self.flushed = Some(chunks);
+        }
+        chunks
+    }
+    fn poll_flush(&mut self) -> Option<SaveStatus> {
+        self.flushed.take().map(|chunks| SaveStatus::Finished { chunks, failed: 0 })
+    }
+}
+
+/// reports how saving is going
+#[derive(Message, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveStatus {
+    /// an autosave started writing ``chunks`` chunks in the background
+    Started { chunks: usize },
+    /// ``done`` of the ``total`` regions being saved are written
+    Progress { done: usize, total: usize },
+    /// the save is done. the chunks of the ``failed`` regions are retried by the next one
+    Finished { chunks: usize, failed: usize },
+}
+
+/// when the last autosave started, and what was last reported about it
+#[derive(Resource, Default)]
+pub struct Autosave {
+    last_save: f32,
+    reported: Option<SaveStatus>,
+}
+
+/// every ``WorldSettings::autosave_interval`` seconds, snapshots the chunks modified since
+/// the last autosave and has the store write them in the background
+pub fn autosave(
+    mut commands: Commands,
+    mut autosave: ResMut<Autosave>,
+    mut store: ResMut<SaveStore>,
+    mut status: MessageWriter<SaveStatus>,
+    settings: Res<WorldSettings>,
+    time: Res<Time>,
+    chunks: Query<(Entity, &Chunk), With<Modified>>,
+) {
+    if let Some(current) = store.0.poll_flush() {
+        if autosave.reported != Some(current) {
+            status.write(current);
+            autosave.reported = Some(current);
+        }
+        if matches!(current, SaveStatus::Progress { .. }) { return; }
+    }
+    if settings.autosave_interval <= 0.0 || time.elapsed_secs() - autosave.last_save < settings.autosave_interval {
+        return;
+    }
+    autosave.last_save = time.elapsed_secs();
+    for (entity, chunk) in chunks {
+        store.0.save(chunk.pos, &chunk.data);
+        commands.entity(entity).remove::<Modified>();
+    }
+    let chunks = store.0.start_flush();
+    if chunks > 0 {
+        let started = SaveStatus::Started { chunks };
+        status.write(started);
+        autosave.reported = Some(started);
+    }
+}
+
+/// saves every modified chunk that is still loaded when the app exits,
+/// and waits for it to be written
+pub fn save_on_exit(
+    mut exit: MessageReader<AppExit>,
+    mut store: ResMut<SaveStore>,
+    chunks: Query<&Chunk, With<Modified>>,
+) {
+    if exit.read().count() == 0 { return; }
+    for chunk in chunks {
+        store.0.save(chunk.pos, &chunk.data);
+    }
+    store.0.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::fast_voxels::{blocks::BlockID, chunk_storage::ChunkData};
+
+    /// an app with ``store`` that autosaves every ``interval`` seconds, and a modified chunk at the origin
+    fn app(store: Box<dyn ChunkStore>, interval: f32) -> (App, Entity) {
+        let mut app = App::new();
+        app.insert_resource(SaveStore(store))
+            .insert_resource(WorldSettings { autosave_interval: interval, ..default() })
+            .init_resource::<Autosave>()
+            .init_resource::<Time>()
+            .add_message::<SaveStatus>()
+            .add_message::<AppExit>();
+        let data = BlockData::new(ChunkData::filled(BlockID::STONE));
+        let entity = app.world_mut().spawn((Chunk { data, pos: IVec3::ZERO }, Modified)).id();
+        (app, entity)
+    }
+
+    /// runs ``autosave`` at ``seconds`` into the game, returning what it reported
+    fn autosave_at(app: &mut App, seconds: u64) -> Vec<SaveStatus> {
+        let world = app.world_mut();
+        let elapsed = world.resource::<Time>().elapsed();
+        world.resource_mut::<Time>().advance_by(Duration::from_secs(seconds) - elapsed);
+        world.run_system_once(autosave).unwrap();
+        world.resource_mut::<Messages<SaveStatus>>().drain().collect()
+    }
+
+    fn is_saved(app: &mut App) -> bool {
+        app.world_mut().resource_mut::<SaveStore>().0.load(IVec3::ZERO).is_some()
+    }
+
+    #[test]
+    fn autosaves_wait_for_the_interval() {
+        let (mut app, entity) = app(Box::new(MemoryStore::default()), 5.0);
+        assert_eq!(autosave_at(&mut app, 4), []);
+        assert!(app.world().get::<Modified>(entity).is_some());
+        assert!(!is_saved(&mut app));
+
+        assert_eq!(autosave_at(&mut app, 5), [SaveStatus::Started { chunks: 1 }]);
+        assert!(app.world().get::<Modified>(entity).is_none());
+        assert!(is_saved(&mut app));
+        assert_eq!(autosave_at(&mut app, 6), [SaveStatus::Finished { chunks: 1, failed: 0 }]);
+        // nothing was modified since
+        assert_eq!(autosave_at(&mut app, 11), []);
+    }
+
+    #[test]
+    fn autosaves_are_off_without_an_interval() {
+        let (mut app, entity) = app(Box::new(MemoryStore::default()), 0.0);
+        assert_eq!(autosave_at(&mut app, 1000), []);
+        assert!(app.world().get::<Modified>(entity).is_some());
+        assert!(!is_saved(&mut app));
+    }
+
+    /// a ``MemoryStore`` whose flushes take ``polls`` polls to finish
+    struct SlowStore {
+        store: MemoryStore,
+        polls: usize,
+        flushing: Option<(usize, usize)>,
+    }
+    impl ChunkStore for SlowStore {
+        fn load(&mut self, pos: IVec3) -> Option<BlockData> {
+            self.store.load(pos)
+        }
+        fn save(&mut self, pos: IVec3, data: &BlockData) {
+            self.store.save(pos, data);
+        }
+        fn start_flush(&mut self) -> usize {
+            let chunks = self.store.start_flush();
+            if chunks > 0 {
+                self.flushing = Some((chunks, self.polls));
+            }
+            chunks
+        }
+        fn poll_flush(&mut self) -> Option<SaveStatus> {
+            let (chunks, polls) = self.flushing.as_mut()?;
+            if *polls > 0 {
+                *polls -= 1;
+                return Some(SaveStatus::Progress { done: 0, total: 1 });
+            }
+            let chunks = *chunks;
+            self.flushing = None;
+            self.store.poll_flush();
+            Some(SaveStatus::Finished { chunks, failed: 0 })
+        }
+    }
+
+    #[test]
+    fn autosaves_report_their_progress_once() {
+        let store = SlowStore { store: MemoryStore::default(), polls: 3, flushing: None };
+        let (mut app, _) = app(Box::new(store), 1.0);
+        let statuses: Vec<SaveStatus> = (1..=5).flat_map(|seconds| autosave_at(&mut app, seconds)).collect();
+        assert_eq!(statuses, [
+            SaveStatus::Started { chunks: 1 },
+            SaveStatus::Progress { done: 0, total: 1 },
+            SaveStatus::Finished { chunks: 1, failed: 0 },
+        ]);
+    }
+
+    #[test]
+    fn modified_chunks_are_saved_on_exit() {
+        let (mut app, _) = app(Box::new(MemoryStore::default()), 0.0);
+        app.world_mut().run_system_once(save_on_exit).unwrap();
+        assert!(!is_saved(&mut app));
+        app.world_mut().write_message(AppExit::Success);
+        app.world_mut().run_system_once(save_on_exit).unwrap();
+        assert!(is_saved(&mut app));
+    }
+}
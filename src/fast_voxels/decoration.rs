@@ -0,0 +1,252 @@
+use std::{cell::OnceCell, sync::Arc};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::fast_voxels::{
+    base_types::{BlockData, CHUNKSIZE},
+    biomes::BiomeMap,
+    blocks::BlockID,
+    chunk_storage::ChunkData,
+    pipeline::{Earlier, Stage, StagePass},
+    world_gen::{SplitMix, hash},
+};
+
+/// a block placed into the world from outside of its chunk, like a part of a structure.
+/// it only ever replaces air, so it cant cut into terrain or into anything built since
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockWrite {
+    /// the world position
+    pub pos: IVec3,
+    pub block: BlockID,
+}
+impl BlockWrite {
+    pub fn new(pos: IVec3, block: BlockID) -> Self {
+        Self { pos, block }
+    }
+    pub fn chunk(&self) -> IVec3 {
+        self.pos.div_euclid(IVec3::splat(CHUNKSIZE as i32))
+    }
+    pub fn local(&self) -> IVec3 {
+        self.pos.rem_euclid(IVec3::splat(CHUNKSIZE as i32))
+    }
+    /// writes the block into ``data``, the chunk it lies in, returning whether it changed anything
+    pub fn apply(&self, data: &mut ChunkData) -> bool {
+        let local = self.local();
+        if data.get(local) != BlockID::AIR {
+            return false;
+        }
+        data.set(local, self.block);
+        true
+    }
+}
+
+/// how ``TreeDecorator`` plants the surface
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TreeSettings {
+    /// the chance for a tree on each ground voxel open to the sky. biomes have their own
+    pub tree_chance: f32,
+    /// the chance for a plant on each ground voxel without a tree
+    pub plant_chance: f32,
+    /// how tall trunks can be
+    pub min_height: i32,
+    pub max_height: i32,
+    /// how far the leaves reach from the top of the trunk
+    pub canopy_radius: f32,
+}
+impl Default for TreeSettings {
+    fn default() -> Self {
+        Self {
+            tree_chance: 0.01,
+            plant_chance: 0.08,
+            min_height: 4,
+            max_height: 7,
+            canopy_radius: 2.5,
+        }
+    }
+}
+
+/// trees of wood and leaves, and plants, on ground that has air above it, in the ``Stage::Decorate`` stage.
+/// each chunk looks for the trees of the chunks around it that reach into it, so trees come out
+/// whole across chunk borders. the chunks around are read as the ``Stage::Ores`` stage left them,
+/// so this replaces queueing the blocks a tree puts into neighbours that arent generated yet:
+/// nothing has to be saved until they are, and a chunk doesnt depend on which neighbours came first
+pub struct TreeDecorator {
+    pub seed: u64,
+    pub settings: TreeSettings,
+    /// if set, how many trees and plants grow depends on the biome
+    pub biomes: Option<Arc<BiomeMap>>,
+}
+impl TreeDecorator {
+    /// how far trees reach from their base, across and up
+    fn extent(&self) -> (i32, i32) {
+        let canopy = self.settings.canopy_radius.ceil() as i32;
+        (canopy, self.settings.max_height.max(self.settings.min_height) + canopy)
+    }
+    /// the tree or plant growing at the world position ``base``, if any
+    fn features(&self, base: IVec3) -> Vec<BlockWrite> {
+        let mut random = SplitMix(hash(hash(self.seed, base.x, base.y), base.z, 0));
+        let (tree_chance, plant_chance) = match &self.biomes {
+            Some(biomes) => {
+                let params = biomes.params(biomes.index_at(base.x, base.z));
+                (params.tree_chance, params.plant_chance)
+            }
+            None => (self.settings.tree_chance, self.settings.plant_chance),
+        };
+        let roll = random.next_f32();
+        if roll < tree_chance {
+            self.tree(base, &mut random)
+        } else if roll < tree_chance + plant_chance {
+            vec![BlockWrite::new(base, BlockID::PLANT)]
+        } else {
+            Vec::new()
+        }
+    }
+    /// the blocks of a tree whose trunk starts at the world position ``base``
+    fn tree(&self, base: IVec3, random: &mut SplitMix) -> Vec<BlockWrite> {
+        let settings = &self.settings;
+        let heights = (settings.max_height - settings.min_height).max(0) as u64 + 1;
+        let height = settings.min_height + (random.next_u64() % heights) as i32;
+        let top = base + IVec3::Y * (height - 1);
+        let radius = settings.canopy_radius;
+        let extent = radius.ceil() as i32;
+        let mut blocks = Vec::new();
+        for x in -extent..=extent {
+            for y in -extent..=extent {
+                for z in -extent..=extent {
+                    let offset = IVec3::new(x, y, z);
+                    // ragged edges look less like a ball
+                    let edge = radius - 0.6 * random.next_f32();
+                    // the trunk goes where the canopy meets it
+                    let trunk = (x, z) == (0, 0) && y <= 0;
+                    if !trunk && offset.as_vec3().length_squared() <= edge * edge {
+                        blocks.push(BlockWrite::new(top + offset, BlockID::LEAF));
+                    }
+                }
+            }
+        }
+        blocks.extend((0..height).map(|y| BlockWrite::new(base + IVec3::Y * y, BlockID::WOOD)));
+        blocks
+    }
+}
+impl StagePass for TreeDecorator {
+    fn stage(&self) -> Stage {
+        Stage::Decorate
+    }
+    fn reach(&self) -> IVec3 {
+        let (across, up) = self.extent();
+        // one more up, for what lies on the ground at the top of the highest chunks trees grow from
+        IVec3::new(across, up, across).map(|extent| (extent + CHUNKSIZE as i32 - 1) / CHUNKSIZE as i32) + IVec3::Y
+    }
+    fn run(&self, chunk_pos: IVec3, data: &mut BlockData, earlier: &Earlier) {
+        let data = Arc::make_mut(data);
+        let (across, up) = self.extent();
+        let chunk_min = chunk_pos * CHUNKSIZE as i32;
+        // the chunks trees can grow from
+        let reach = self.reach() - IVec3::Y;
+        // always in the same order, so where trees overlap the same one wins
+        for dx in -reach.x..=reach.x {
+            for dy in -reach.y..=reach.y {
+                for dz in -reach.z..=reach.z {
+                    let source_pos = chunk_pos + IVec3::new(dx, dy, dz);
+                    let source = earlier.get(source_pos);
+                    if source.uniform().is_some_and(|block| block != BlockID::GROUND) { continue; }
+                    let above = OnceCell::new();
+                    let origin = source_pos * CHUNKSIZE as i32;
+                    for x in 0..CHUNKSIZE as i32 {
+                        for z in 0..CHUNKSIZE as i32 {
+                            let column = origin + IVec3::new(x, 0, z);
+                            let near = |pos: i32, min: i32| (min - across..min + CHUNKSIZE as i32 + across).contains(&pos);
+                            if !near(column.x, chunk_min.x) || !near(column.z, chunk_min.z) { continue; }
+                            // the highest ground in the column with air on top, which is in the chunk above at the top
+                            let Some(y) = (0..CHUNKSIZE as i32).rev().find(|y| {
+                                source.get(IVec3::new(x, *y, z)) == BlockID::GROUND && match y + 1 {
+                                    top if top < CHUNKSIZE as i32 => source.get(IVec3::new(x, top, z)),
+                                    _ => above.get_or_init(|| earlier.get(source_pos + IVec3::Y)).get(IVec3::new(x, 0, z)),
+                                } == BlockID::AIR
+                            }) else { continue; };
+                            let base = column + IVec3::Y * (y + 1);
+                            if !(chunk_min.y - up..chunk_min.y + CHUNKSIZE as i32 + across).contains(&base.y) { continue; }
+                            for block in self.features(base) {
+                                if block.chunk() == chunk_pos {
+                                    block.apply(data);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+    use crate::fast_voxels::{
+        chunk_storage::{CHUNK_VOLUME, ChunkStorage},
+        pipeline::GenerationPipeline,
+        region_store::diff_chunk,
+        world_gen::{FlatGenerator, WorldGenerator},
+    };
+
+    #[test]
+    fn ground_at_the_top_of_a_chunk_is_planted() {
+        // the chunks at y 0 are all ground, so the surface is the top of them
+        let pipeline = GenerationPipeline::new(Arc::new(FlatGenerator { height: CHUNKSIZE as i32, block: BlockID::GROUND }))
+            .with_pass(Arc::new(TreeDecorator { seed: 1, settings: TreeSettings::default(), biomes: None }));
+        let above = pipeline.generate(IVec3::Y);
+        let bottom: Vec<BlockID> = (0..CHUNK_VOLUME)
+            .map(ChunkStorage::position)
+            .filter(|pos| pos.y == 0)
+            .map(|pos| above.get(pos))
+            .collect();
+        assert!(bottom.contains(&BlockID::WOOD));
+        assert!(bottom.contains(&BlockID::PLANT));
+        assert_eq!(pipeline.generate(IVec3::ZERO).uniform(), Some(BlockID::GROUND));
+    }
+
+    /// a pipeline of flat ground up to ``height`` with sparse trees and no plants
+    fn forest(seed: u64, height: i32) -> (GenerationPipeline, TreeDecorator) {
+        let settings = TreeSettings { tree_chance: 0.004, plant_chance: 0.0, ..default() };
+        let decorator = || TreeDecorator { seed, settings: settings.clone(), biomes: None };
+        let pipeline = GenerationPipeline::new(Arc::new(FlatGenerator { height, block: BlockID::GROUND }))
+            .with_pass(Arc::new(decorator()));
+        (pipeline, decorator())
+    }
+
+    #[test]
+    fn trees_across_chunk_borders_come_out_whole_in_any_order() {
+        // the surface is near the top of the chunks at y 0, so canopies reach into the ones above
+        let height = CHUNKSIZE as i32 - 4;
+        // the first tree by a chunk corner, whose blocks lie in chunks on every side of it
+        let (seed, tree) = (0..)
+            .find_map(|seed| {
+                let (_, decorator) = forest(seed, height);
+                let columns = (CHUNKSIZE as i32 - 2..CHUNKSIZE as i32 + 2).flat_map(|x| (CHUNKSIZE as i32 - 2..CHUNKSIZE as i32 + 2).map(move |z| (x, z)));
+                columns
+                    .map(|(x, z)| decorator.features(IVec3::new(x, height, z)))
+                    .find(|tree| tree.iter().map(BlockWrite::chunk).collect::<HashSet<_>>().len() == 8)
+                    .map(|tree| (seed, tree))
+            })
+            .unwrap();
+        let chunks: Vec<IVec3> = tree.iter().map(BlockWrite::chunk).collect::<HashSet<_>>().into_iter().collect();
+        let (reference, _) = forest(seed, height);
+        for first in &chunks {
+            let (pipeline, _) = forest(seed, height);
+            let mut generated = HashMap::new();
+            for pos in std::iter::once(first).chain(&chunks) {
+                generated.entry(*pos).or_insert_with(|| pipeline.generate(*pos));
+            }
+            for block in &tree {
+                assert_eq!(generated[&block.chunk()].get(block.local()), block.block, "{block:?} with chunk {first} generated first");
+            }
+            for pos in &chunks {
+                assert!(diff_chunk(&generated[pos], &reference.generate(*pos)).is_empty(), "chunk {pos} with chunk {first} generated first");
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use bevy::math::IVec3;
+
+use crate::fast_voxels::{
+    base_types::{CHUNKSIZE, FAST_CHUNKSIZE, FastChunk},
+    blocks::{BlockID, BlockState},
+    chunk_storage::{CHUNK_VOLUME, ChunkData, ChunkStorage, PalettedStorage, StateStorage},
+    neighborhood::ChunkNeighborhood,
+};
+
+const SIZE: i32 = FAST_CHUNKSIZE as i32;
+
+impl FastChunk {
+    /// the world position of the first voxel of the apron of the fast chunk at ``pos``
+    pub fn origin(pos: IVec3) -> IVec3 {
+        pos * SIZE - IVec3::ONE
+    }
+    /// the chunk a ``ChunkNeighborhood`` has to be centred on to contain the whole
+    /// fast chunk at ``pos``, apron included
+    pub fn source_chunk(pos: IVec3) -> IVec3 {
+        (pos * SIZE).div_euclid(IVec3::splat(CHUNKSIZE as i32))
+    }
+    /// copies the fast chunk at ``pos`` and its apron out of ``neighborhood``, which has to
+    /// be centred on ``FastChunk::source_chunk(pos)``
+    pub fn from_neighborhood(pos: IVec3, neighborhood: &ChunkNeighborhood) -> Self {
+        debug_assert_eq!(
+            neighborhood.pos,
+            Self::source_chunk(pos),
+            "the neighbourhood doesnt contain fast chunk {pos}",
+        );
+        let offset = Self::origin(pos) - neighborhood.pos * CHUNKSIZE as i32;
+        let mut data = PalettedStorage::filled(neighborhood.get(offset));
+        let mut states = StateStorage::Empty;
+        for index in 0..CHUNK_VOLUME {
+            let local = offset + ChunkStorage::position(index);
+            data.set(index, neighborhood.get(local));
+            states.set(index, neighborhood.state(local));
+        }
+        Self {
+            pos,
+            data,
+            states,
+        }
+    }
+    #[inline]
+    pub fn get(&self, local: IVec3) -> BlockID {
+        self.data.get(ChunkStorage::index(local))
+    }
+    #[inline]
+    pub fn state(&self, local: IVec3) -> BlockState {
+        self.states.get(ChunkStorage::index(local))
+    }
+    /// copies the interior voxels that lie in the chunk at ``chunk_pos`` into ``data``.
+    /// the apron is only a copy of the neighbours, so it is never written back
+    pub fn export_interior(&self, chunk_pos: IVec3, data: &mut ChunkData) {
+        let origin = Self::origin(self.pos);
+        let chunk_min = chunk_pos * CHUNKSIZE as i32;
+        let min = (self.pos * SIZE).max(chunk_min);
+        let max = (self.pos * SIZE + SIZE).min(chunk_min + CHUNKSIZE as i32);
+        for x in min.x..max.x {
+            for y in min.y..max.y {
+                for z in min.z..max.z {
+                    let world_pos = IVec3::new(x, y, z);
+                    let local = world_pos - origin;
+                    data.set_with_state(world_pos - chunk_min, self.get(local), self.state(local));
+                }
+            }
+        }
+    }
+    /// the fast chunks whose interior overlaps the chunk at ``chunk_pos``.
+    /// exporting all of them rebuilds the whole chunk
+    pub fn overlapping(chunk_pos: IVec3) -> impl Iterator<Item = IVec3> {
+        let chunk_min = chunk_pos * CHUNKSIZE as i32;
+        Self::grid_range(
+            chunk_min.div_euclid(IVec3::splat(SIZE)),
+            (chunk_min + IVec3::splat(CHUNKSIZE as i32 - 1)).div_euclid(IVec3::splat(SIZE)),
+        )
+    }
+    /// the fast chunks whose interior or apron contain the world voxel ``world_pos``.
+    /// a voxel on the border of a fast chunk is also in the aprons of up to 7 others
+    pub fn containing(world_pos: IVec3) -> impl Iterator<Item = IVec3> {
+        Self::grid_range(
+            (world_pos - IVec3::ONE).div_euclid(IVec3::splat(SIZE)),
+            (world_pos + IVec3::ONE).div_euclid(IVec3::splat(SIZE)),
+        )
+    }
+    /// writes an edit to the voxel at ``world_pos`` if it lies in this fast chunk or its apron,
+    /// so an edit to a neighbour's border voxel updates the apron without copying it again.
+    /// returns whether anything changed
+    pub fn apply_edit(&mut self, world_pos: IVec3, block: BlockID, state: BlockState) -> bool {
+        let local = world_pos - Self::origin(self.pos);
+        if local.min_element() < 0 || local.max_element() >= CHUNKSIZE as i32 {
+            return false;
+        }
+        let index = ChunkStorage::index(local);
+        if self.data.get(index) == block && self.states.get(index) == state {
+            return false;
+        }
+        self.data.set(index, block);
+        self.states.set(index, state);
+        true
+    }
+    /// every fast chunk position from ``min`` to ``max``, inclusive
+    fn grid_range(min: IVec3, max: IVec3) -> impl Iterator<Item = IVec3> {
+        (min.x..=max.x).flat_map(move |x| {
+            (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| IVec3::new(x, y, z)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use super::*;
+    use crate::fast_voxels::{base_types::BlockData, neighborhood::MissingNeighbor, world_gen::SplitMix};
+
+    /// a chunk of its own block with a few hundred random voxels and states, the same for every ``pos``
+    fn chunk_at(pos: IVec3) -> BlockData {
+        let mut random = SplitMix(pos.x as u64 ^ ((pos.y as u64) << 21) ^ ((pos.z as u64) << 42));
+        let mut data = ChunkData::filled(BlockID((random.next_u64() % 8) as u16));
+        for _ in 0..300 {
+            let local = ChunkStorage::position((random.next_u64() % CHUNK_VOLUME as u64) as usize);
+            let block = BlockID((random.next_u64() % 8) as u16);
+            data.set_with_state(local, block, BlockState((random.next_u64() % 4) as u8));
+        }
+        Arc::new(data)
+    }
+
+    /// the fast chunk at ``pos``, copied out of the neighbourhood it needs
+    fn fast_chunk(pos: IVec3, world: &mut HashMap<IVec3, BlockData>) -> FastChunk {
+        let source = FastChunk::source_chunk(pos);
+        let neighborhood = ChunkNeighborhood::from_fn(source, MissingNeighbor::Defer, |pos| {
+            Some(world.entry(pos).or_insert_with(|| chunk_at(pos)).clone())
+        })
+        .unwrap();
+        FastChunk::from_neighborhood(pos, &neighborhood)
+    }
+
+    /// the block and state of the world voxel at ``world_pos``
+    fn voxel(world_pos: IVec3, world: &mut HashMap<IVec3, BlockData>) -> (BlockID, BlockState) {
+        let size = CHUNKSIZE as i32;
+        let pos = world_pos.div_euclid(IVec3::splat(size));
+        let chunk = world.entry(pos).or_insert_with(|| chunk_at(pos));
+        let local = world_pos.rem_euclid(IVec3::splat(size));
+        (chunk.get(local), chunk.state(local))
+    }
+
+    #[test]
+    fn chunks_round_trip_through_fast_chunks() {
+        let mut world = HashMap::new();
+        for chunk_pos in [IVec3::ZERO, IVec3::NEG_ONE, IVec3::new(14, -15, 1)] {
+            let mut data = ChunkData::filled(BlockID::AIR);
+            for pos in FastChunk::overlapping(chunk_pos) {
+                let fast = fast_chunk(pos, &mut world);
+                // the apron is a copy of the neighbours, so every voxel matches the world
+                for index in 0..CHUNK_VOLUME {
+                    let local = ChunkStorage::position(index);
+                    let expected = voxel(FastChunk::origin(pos) + local, &mut world);
+                    assert_eq!((fast.get(local), fast.state(local)), expected, "voxel {local} of fast chunk {pos}");
+                }
+                fast.export_interior(chunk_pos, &mut data);
+            }
+            let original = world[&chunk_pos].clone();
+            for index in 0..CHUNK_VOLUME {
+                let local = ChunkStorage::position(index);
+                assert_eq!(
+                    (data.get(local), data.state(local)),
+                    (original.get(local), original.state(local)),
+                    "voxel {local} of chunk {chunk_pos}",
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn edits_update_the_apron() {
+        let mut world = HashMap::new();
+        // a corner of fast chunk 0, which is also in the aprons of the 7 around it
+        let world_pos = IVec3::splat(SIZE - 1);
+        let containing = FastChunk::containing(world_pos).collect::<Vec<_>>();
+        assert_eq!(containing.len(), 8);
+        let (block, state) = (BlockID(100), BlockState(3));
+        for x in -1..=2 {
+            for y in -1..=2 {
+                for z in -1..=2 {
+                    let pos = IVec3::new(x, y, z);
+                    let mut fast = fast_chunk(pos, &mut world);
+                    assert_eq!(fast.apply_edit(world_pos, block, state), containing.contains(&pos), "fast chunk {pos}");
+                    if containing.contains(&pos) {
+                        let local = world_pos - FastChunk::origin(pos);
+                        assert_eq!((fast.get(local), fast.state(local)), (block, state));
+                        assert!(!fast.apply_edit(world_pos, block, state), "the same edit twice changes fast chunk {pos}");
+                    }
+                }
+            }
+        }
+    }
+}
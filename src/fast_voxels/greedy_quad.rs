@@ -54,10 +54,10 @@ impl GreedyQuad {
         unsafe { std::mem::transmute(val) }
     }
     pub fn set_block_type(&mut self, block: GPUBlockID) {
-        self.data = self.data & !(16 << 28) | ((block as u32) << 28);
+        debug_assert!((block.0 as usize) < GPUBlockID::COUNT, "gpu block id {} doesnt fit in 4 bits", block.0);
+        self.data = self.data & !(0b1_111 << 28) | ((block.0 as u32) << 28);
     }
     pub fn get_block_type(&self) -> GPUBlockID {
-        let val = ((self.data >> 28) & 0b1_111) as u8;
-        unsafe { std::mem::transmute(val) }
+        GPUBlockID(((self.data >> 28) & 0b1_111) as u8)
     }
 }
\ No newline at end of file
@@ -0,0 +1,360 @@
+use std::{fs, io, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::fast_voxels::{
+    base_types::{BlockData, CHUNKSIZE},
+    block_registry::BlockRegistry,
+    blocks::BlockID,
+    chunk_storage::ChunkData,
+    structures::ASSET_DIR,
+    world_gen::{Column, WorldGenerator},
+};
+
+/// what a ``HeightmapGenerator`` does past the edges of its images
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Edges {
+    /// the pixels at the edge go on forever
+    #[default]
+    Clamp,
+    /// the images repeat
+    Tile,
+}
+
+/// a colour of the material map, and the name of the block it stands for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaterialColor {
+    pub color: [u8; 3],
+    pub block: String,
+}
+
+/// how ``HeightmapGenerator`` reads its images
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeightmapSettings {
+    /// the grayscale png the surface is read from, relative to the assets folder.
+    /// its x goes along the world's x and its rows along z, starting at the origin
+    pub heightmap: String,
+    /// a png as large as ``heightmap`` whose colours pick the block each column is covered with.
+    /// without it every column is covered with ground
+    pub materials: Option<String>,
+    /// the blocks the colours of ``materials`` stand for. pixels of other colours,
+    /// like the ones on soft brush edges, take the closest one
+    pub colors: Vec<MaterialColor>,
+    /// how many voxels wide each pixel is
+    pub scale: f32,
+    /// the world y of the lowest voxel above the surface where the heightmap is black
+    pub min_y: i32,
+    /// and where it is white
+    pub max_y: i32,
+    /// how many voxels of cover lie on the stone
+    pub depth: i32,
+    /// the world y of the lowest voxel that isnt filled with water
+    pub sea_level: i32,
+    pub edges: Edges,
+}
+impl Default for HeightmapSettings {
+    fn default() -> Self {
+        Self {
+            heightmap: "heightmap.png".to_string(),
+            materials: None,
+            colors: Vec::new(),
+            scale: 1.0,
+            min_y: 0,
+            max_y: 128,
+            depth: 4,
+            sea_level: 0,
+            edges: Edges::Clamp,
+        }
+    }
+}
+
+/// a decoded png, with every sample widened to 16 bits
+struct Image {
+    width: i32,
+    height: i32,
+    channels: usize,
+    samples: Vec<u16>,
+}
+impl Image {
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut decoder = png::Decoder::new(io::Cursor::new(bytes));
+        // palettes become colours, and grays of less than 8 bits become bytes
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info()?;
+        let size = reader.output_buffer_size()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "the image is too large"))?;
+        let mut buffer = vec![0; size];
+        let info = reader.next_frame(&mut buffer)?;
+        let channels = info.color_type.samples();
+        let mut samples = Vec::with_capacity(info.width as usize * info.height as usize * channels);
+        for line in buffer.chunks(info.line_size).take(info.height as usize) {
+            let line = &line[..info.width as usize * channels * (info.bit_depth as usize / 8)];
+            match info.bit_depth {
+                png::BitDepth::Sixteen => samples.extend(line.chunks_exact(2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))),
+                _ => samples.extend(line.iter().map(|byte| u16::from(*byte) * 257)),
+            }
+        }
+        Ok(Self {
+            width: info.width as i32,
+            height: info.height as i32,
+            channels,
+            samples,
+        })
+    }
+    /// the colour samples of every pixel, without alpha, row by row
+    fn pixels(&self) -> impl Iterator<Item = &[u16]> {
+        let colors = if self.channels % 2 == 0 { self.channels - 1 } else { self.channels };
+        self.samples.chunks_exact(self.channels).map(move |pixel| &pixel[..colors])
+    }
+}
+
+/// terrain painted as images: the surface comes from the brightness of a heightmap, between
+/// ``HeightmapSettings::min_y`` and ``HeightmapSettings::max_y``, and the block covering it from
+/// the colours of a material map, with stone below and water up to the sea level
+pub struct HeightmapGenerator {
+    settings: HeightmapSettings,
+    width: i32,
+    height: i32,
+    /// the brightness of every pixel of the heightmap, from 0 to 1
+    heights: Vec<f32>,
+    /// the cover block of every pixel
+    covers: Vec<BlockID>,
+}
+impl HeightmapGenerator {
+    /// the generator for the images ``settings`` names
+    pub fn open(settings: HeightmapSettings, registry: &BlockRegistry) -> io::Result<Self> {
+        let read = |path: &str| fs::read(Path::new(ASSET_DIR).join(path))
+            .map_err(|err| io::Error::new(err.kind(), format!("couldnt read {path}: {err}")));
+        let heightmap = read(&settings.heightmap)?;
+        let materials = settings.materials.as_deref().map(read).transpose()?;
+        Self::new(settings, &heightmap, materials.as_deref(), registry)
+    }
+    /// the generator for the png files ``heightmap`` and ``materials``
+    pub fn new(settings: HeightmapSettings, heightmap: &[u8], materials: Option<&[u8]>, registry: &BlockRegistry) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        if settings.scale.is_nan() || settings.scale <= 0.0 {
+            return Err(invalid(format!("a heightmap cant be scaled by {}", settings.scale)));
+        }
+        let heightmap = Image::decode(heightmap)
+            .map_err(|err| io::Error::new(err.kind(), format!("invalid heightmap {}: {err}", settings.heightmap)))?;
+        let heights = heightmap.pixels()
+            .map(|pixel| pixel.iter().map(|sample| f32::from(*sample)).sum::<f32>() / (pixel.len() as f32 * f32::from(u16::MAX)))
+            .collect();
+
+        let covers = match materials {
+            Some(materials) => {
+                let materials = Image::decode(materials)
+                    .map_err(|err| io::Error::new(err.kind(), format!("invalid material map: {err}")))?;
+                if (materials.width, materials.height) != (heightmap.width, heightmap.height) {
+                    return Err(invalid(format!(
+                        "the material map is {}x{}, but the heightmap is {}x{}",
+                        materials.width, materials.height, heightmap.width, heightmap.height,
+                    )));
+                }
+                let colors = settings.colors.iter()
+                    .map(|color| match registry.by_name(&color.block) {
+                        Some(block) => Ok((color.color.map(i32::from), block)),
+                        None => Err(invalid(format!("material colour {:?} is block {:?}, which isnt registered", color.color, color.block))),
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+                if colors.is_empty() {
+                    return Err(invalid("a material map needs at least one colour".to_string()));
+                }
+                materials.pixels()
+                    .map(|pixel| {
+                        // grays have one sample for all three
+                        let color = [0, 1, 2].map(|i| i32::from(pixel[i.min(pixel.len() - 1)] >> 8));
+                        let distance = |other: &[i32; 3]| (0..3).map(|i| (color[i] - other[i]).pow(2)).sum::<i32>();
+                        colors.iter().min_by_key(|(other, _)| distance(other)).map_or(BlockID::GROUND, |(_, block)| *block)
+                    })
+                    .collect()
+            }
+            None => vec![BlockID::GROUND; heightmap.width as usize * heightmap.height as usize],
+        };
+        Ok(Self {
+            settings,
+            width: heightmap.width,
+            height: heightmap.height,
+            heights,
+            covers,
+        })
+    }
+    /// the index of the pixel at ``x``, ``y`` of the images, following ``HeightmapSettings::edges`` past them
+    fn pixel(&self, x: i32, y: i32) -> usize {
+        let (x, y) = match self.settings.edges {
+            Edges::Clamp => (x.clamp(0, self.width - 1), y.clamp(0, self.height - 1)),
+            Edges::Tile => (x.rem_euclid(self.width), y.rem_euclid(self.height)),
+        };
+        (y * self.width + x) as usize
+    }
+    /// the world y of the lowest voxel above the surface of the column at ``x``, ``z``.
+    /// the heightmap is blended between pixels, so scaled up images dont come out as steps
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        // pixel centres lie in the middle of the voxels they cover
+        let pos = (Vec2::new(x as f32, z as f32) + 0.5) / self.settings.scale - 0.5;
+        let corner = pos.floor();
+        let fraction = pos - corner;
+        let (x, y) = (corner.x as i32, corner.y as i32);
+        let at = |dx, dy| self.heights[self.pixel(x + dx, y + dy)];
+        let top = at(0, 0) + (at(1, 0) - at(0, 0)) * fraction.x;
+        let bottom = at(0, 1) + (at(1, 1) - at(0, 1)) * fraction.x;
+        let brightness = top + (bottom - top) * fraction.y;
+        let settings = &self.settings;
+        settings.min_y + (brightness * (settings.max_y - settings.min_y) as f32).round() as i32
+    }
+    pub fn column_at(&self, x: i32, z: i32) -> Column {
+        let pixel = (Vec2::new(x as f32, z as f32) + 0.5) / self.settings.scale;
+        Column {
+            surface: self.height_at(x, z),
+            cover: self.covers[self.pixel(pixel.x.floor() as i32, pixel.y.floor() as i32)],
+            depth: self.settings.depth,
+        }
+    }
+    /// the block at world height ``y`` of ``column``
+    fn block_at(&self, y: i32, column: &Column) -> BlockID {
+        if y < column.surface - column.depth {
+            BlockID::STONE
+        } else if y < column.surface {
+            column.cover
+        } else if y < self.settings.sea_level {
+            BlockID::WATER
+        } else {
+            BlockID::AIR
+        }
+    }
+}
+impl WorldGenerator for HeightmapGenerator {
+    fn generate(&self, chunk_pos: IVec3) -> BlockData {
+        let settings = &self.settings;
+        let origin = chunk_pos * CHUNKSIZE as i32;
+        let (bottom, top) = (settings.min_y.min(settings.max_y), settings.min_y.max(settings.max_y));
+        if origin.y >= top.max(settings.sea_level) {
+            return BlockData::new(ChunkData::filled(BlockID::AIR));
+        }
+        if origin.y + (CHUNKSIZE as i32) <= bottom - settings.depth.max(0) {
+            return BlockData::new(ChunkData::filled(BlockID::STONE));
+        }
+        let mut data = ChunkData::filled(BlockID::AIR);
+        for x in 0..CHUNKSIZE as i32 {
+            for z in 0..CHUNKSIZE as i32 {
+                let column = self.column_at(origin.x + x, origin.z + z);
+                for y in 0..CHUNKSIZE as i32 {
+                    let block = self.block_at(origin.y + y, &column);
+                    if block != BlockID::AIR {
+                        data.set(IVec3::new(x, y, z), block);
+                    }
+                }
+            }
+        }
+        BlockData::new(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a png of ``width`` by ``height`` pixels with the raw ``samples``
+    fn png(width: u32, height: u32, color: png::ColorType, depth: png::BitDepth, samples: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(samples).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+    fn gray(width: u32, height: u32, samples: &[u8]) -> Vec<u8> {
+        png(width, height, png::ColorType::Grayscale, png::BitDepth::Eight, samples)
+    }
+    fn settings(scale: f32, max_y: i32) -> HeightmapSettings {
+        HeightmapSettings { scale, max_y, ..default() }
+    }
+    fn open(settings: HeightmapSettings, heightmap: &[u8], materials: Option<&[u8]>) -> HeightmapGenerator {
+        HeightmapGenerator::new(settings, heightmap, materials, &BlockRegistry::default()).unwrap()
+    }
+
+    #[test]
+    fn scaled_heightmaps_are_blended() {
+        let generator = open(settings(4.0, 100), &gray(2, 2, &[0, 255, 0, 255]), None);
+        let heights = (0..8).map(|x| generator.height_at(x, 0)).collect::<Vec<_>>();
+        assert_eq!(heights, [0, 0, 13, 38, 63, 88, 100, 100]);
+        // the rows are the same, so z doesnt matter
+        assert!((0..8).all(|z| generator.height_at(3, z) == 38));
+    }
+
+    #[test]
+    fn sixteen_bit_heightmaps_keep_their_precision() {
+        let samples = [0x80, 0x00, 0x00, 0x01];
+        let heightmap = png(2, 1, png::ColorType::Grayscale, png::BitDepth::Sixteen, &samples);
+        let generator = open(settings(1.0, u16::MAX as i32), &heightmap, None);
+        // eight bits would round these to 0x8080 and 0
+        assert_eq!(generator.height_at(0, 0), 0x8000);
+        assert_eq!(generator.height_at(1, 0), 1);
+    }
+
+    #[test]
+    fn alpha_is_ignored() {
+        let heightmap = png(2, 1, png::ColorType::GrayscaleAlpha, png::BitDepth::Eight, &[255, 0, 0, 255]);
+        let generator = open(settings(1.0, 100), &heightmap, None);
+        assert_eq!(generator.height_at(0, 0), 100);
+        assert_eq!(generator.height_at(1, 0), 0);
+    }
+
+    #[test]
+    fn edges_clamp_or_tile() {
+        let samples = [
+            0, 85, 170, 255,
+            0, 85, 170, 255,
+            0, 85, 170, 255,
+            0, 85, 170, 255,
+        ];
+        let heightmap = gray(4, 4, &samples);
+        let clamped = open(settings(1.0, 255), &heightmap, None);
+        assert_eq!(clamped.height_at(-100, 2), 0);
+        assert_eq!(clamped.height_at(100, -100), 255);
+        assert_eq!(clamped.height_at(2, 100), 170);
+
+        let tiled = open(HeightmapSettings { edges: Edges::Tile, ..settings(1.0, 255) }, &heightmap, None);
+        assert_eq!(tiled.height_at(-1, 2), 255);
+        assert_eq!(tiled.height_at(5, -7), 85);
+        assert!((-8..8).all(|x| tiled.height_at(x, 0) == tiled.height_at(x + 4, 9)));
+    }
+
+    #[test]
+    fn materials_pick_the_closest_colour() {
+        let colors = vec![
+            MaterialColor { color: [255, 0, 0], block: "stone".to_string() },
+            MaterialColor { color: [0, 0, 255], block: "wood".to_string() },
+            MaterialColor { color: [255, 255, 255], block: "water".to_string() },
+        ];
+        let settings = HeightmapSettings { colors, ..settings(2.0, 100) };
+        let heightmap = gray(2, 2, &[128; 4]);
+        let materials = png(2, 2, png::ColorType::Rgb, png::BitDepth::Eight, &[
+            255, 0, 0,     0, 0, 255,
+            200, 40, 30,   240, 250, 235,
+        ]);
+        let generator = open(settings.clone(), &heightmap, Some(&materials));
+        // each pixel covers two by two columns
+        assert_eq!(generator.column_at(1, 1).cover, BlockID::STONE);
+        assert_eq!(generator.column_at(2, 0).cover, BlockID::WOOD);
+        assert_eq!(generator.column_at(0, 3).cover, BlockID::STONE);
+        assert_eq!(generator.column_at(3, 2).cover, BlockID::WATER);
+
+        // grays stand for all three channels
+        let gray_materials = gray(2, 2, &[255, 250, 0, 10]);
+        let gray_generator = open(settings.clone(), &heightmap, Some(&gray_materials));
+        assert_eq!(gray_generator.column_at(0, 0).cover, BlockID::WATER);
+        assert_eq!(gray_generator.column_at(2, 0).cover, BlockID::WATER);
+
+        let registry = BlockRegistry::default();
+        let small = gray(1, 1, &[0]);
+        assert!(HeightmapGenerator::new(settings.clone(), &heightmap, Some(&small), &registry).is_err());
+        let unknown = vec![MaterialColor { color: [0, 0, 0], block: "cheese".to_string() }];
+        let unknown = HeightmapSettings { colors: unknown, ..settings };
+        assert!(HeightmapGenerator::new(unknown, &heightmap, Some(&materials), &registry).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+use std::mem::size_of_val;
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, FrameCount},
+    prelude::*,
+};
+
+use crate::fast_voxels::{
+    base_types::{BlockData, Chunk, Quad, VoxelMesh},
+    chunk_manager::{ChunkManager, DirtyChunks, Modified},
+    chunk_store::SaveStore,
+    world_gen::WorldGen,
+};
+
+pub const BLOCK_BYTES: DiagnosticPath = DiagnosticPath::const_new("chunks/block_bytes");
+pub const MESH_BYTES: DiagnosticPath = DiagnosticPath::const_new("chunks/mesh_bytes");
+pub const LOADED_CHUNKS: DiagnosticPath = DiagnosticPath::const_new("chunks/loaded");
+pub const CACHE_BYTES: DiagnosticPath = DiagnosticPath::const_new("chunks/cache_bytes");
+
+/// the diagnostics reported by ``enforce_memory_budget``
+pub fn diagnostics() -> [Diagnostic; 4] {
+    [
+        Diagnostic::new(BLOCK_BYTES).with_suffix(" B"),
+        Diagnostic::new(MESH_BYTES).with_suffix(" B"),
+        Diagnostic::new(LOADED_CHUNKS),
+        Diagnostic::new(CACHE_BYTES).with_suffix(" B"),
+    ]
+}
+
+/// how many bytes loaded chunks may use before the least recently visible ones are evicted
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct MemoryBudget {
+    pub block_bytes: usize,
+    pub mesh_bytes: usize,
+    /// how many bytes the ``WorldGenerator`` may keep cached
+    pub cache_bytes: usize,
+}
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self {
+            block_bytes: 256 * 1024 * 1024,
+            mesh_bytes: 128 * 1024 * 1024,
+            cache_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// the bytes used by one chunk's block data and mesh
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct ChunkMemory {
+    pub block_bytes: usize,
+    pub mesh_bytes: usize,
+}
+impl ChunkMemory {
+    pub fn of_blocks(data: &BlockData) -> usize {
+        size_of_val(&**data) + data.heap_size()
+    }
+    pub fn of_mesh(mesh: &VoxelMesh) -> usize {
+        mesh.quads.iter().map(|quads| quads.capacity() * size_of::<Quad>()).sum()
+    }
+}
+
+/// the last frame the chunk was inside the render distance
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LastVisible(pub u32);
+
+/// marks a chunk whose mesh was dropped to stay inside the mesh budget.
+/// it is remeshed once it becomes visible again
+#[derive(Component)]
+pub struct MeshEvicted;
+
+/// keeps ``ChunkMemory`` up to date when a chunk's blocks or mesh change
+pub fn account_chunk_memory(
+    mut chunks: Query<(&Chunk, Option<&VoxelMesh>, &mut ChunkMemory), Or<(Changed<Chunk>, Changed<VoxelMesh>)>>,
+) {
+    for (chunk, mesh, mut memory) in &mut chunks {
+        memory.block_bytes = ChunkMemory::of_blocks(&chunk.data);
+        memory.mesh_bytes = mesh.map_or(0, ChunkMemory::of_mesh);
+    }
+}
+
+/// evicts the least recently visible chunks until the loaded chunks fit the budget.
+/// meshes are dropped first while only the mesh budget is exceeded, and whole chunks are
+/// unloaded (and saved, if they were modified) while the block budget is exceeded.
+/// chunks that are visible this frame are never evicted.
+/// saved chunks the store hasnt written yet count against the block budget, and evicting modified
+/// chunks or exceeding the budget with them starts writing them, as autosaves might be off.
+/// the generator's caches have their own budget, and forget what was used longest ago
+pub fn enforce_memory_budget(
+    mut commands: Commands,
+    budget: Res<MemoryBudget>,
+    world_gen: Res<WorldGen>,
+    frame: Res<FrameCount>,
+    mut chunk_manager: ResMut<ChunkManager>,
+    mut dirty: ResMut<DirtyChunks>,
+    mut store: ResMut<SaveStore>,
+    mut chunks: Query<(Entity, &Chunk, &mut ChunkMemory, &LastVisible, Has<Modified>)>,
+    mut diagnostics: Diagnostics,
+) {
+    let unwritten = store.0.unwritten_bytes();
+    let mut block_bytes: usize = chunks.iter().map(|(_, _, memory, ..)| memory.block_bytes).sum::<usize>() + unwritten;
+    let mut flush = unwritten > 0 && block_bytes > budget.block_bytes;
+    let mut mesh_bytes: usize = chunks.iter().map(|(_, _, memory, ..)| memory.mesh_bytes).sum();
+    let mut loaded = chunks.iter().len();
+
+    if block_bytes > budget.block_bytes || mesh_bytes > budget.mesh_bytes {
+        let mut candidates: Vec<_> = chunks.iter_mut()
+            .filter(|(.., last_visible, _)| last_visible.0 < frame.0)
+            .collect();
+        candidates.sort_unstable_by_key(|(.., last_visible, _)| **last_visible);
+
+        for (entity, chunk, mut memory, _, modified) in candidates {
+            if block_bytes > budget.block_bytes {
+                if modified {
+                    store.0.save(chunk.pos, &chunk.data);
+                    flush = true;
+                }
+                chunk_manager.remove_chunk(&mut commands, &mut dirty, chunk.pos);
+                block_bytes -= memory.block_bytes;
+                mesh_bytes -= memory.mesh_bytes;
+                loaded -= 1;
+            } else if mesh_bytes > budget.mesh_bytes {
+                if memory.mesh_bytes == 0 { continue; }
+                commands.entity(entity)
+                    .remove::<VoxelMesh>()
+                    .insert(MeshEvicted);
+                mesh_bytes -= memory.mesh_bytes;
+                memory.mesh_bytes = 0;
+            } else {
+                break;
+            }
+        }
+    }
+
+    // the chunks being written still count until the flush is done
+    if flush {
+        store.0.start_flush();
+    }
+
+    let mut cache_bytes = world_gen.0.cache_bytes();
+    if cache_bytes > budget.cache_bytes {
+        world_gen.0.trim_cache(budget.cache_bytes);
+        cache_bytes = world_gen.0.cache_bytes();
+    }
+
+    diagnostics.add_measurement(&BLOCK_BYTES, || block_bytes as f64);
+    diagnostics.add_measurement(&MESH_BYTES, || mesh_bytes as f64);
+    diagnostics.add_measurement(&LOADED_CHUNKS, || loaded as f64);
+    diagnostics.add_measurement(&CACHE_BYTES, || cache_bytes as f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        mem,
+        sync::{Arc, Mutex},
+    };
+
+    use bevy::{diagnostic::RegisterDiagnostic, ecs::system::RunSystemOnce};
+
+    use super::*;
+    use crate::fast_voxels::{
+        blocks::BlockID,
+        chunk_storage::ChunkData,
+        chunk_store::ChunkStore,
+        world_gen::FlatGenerator,
+    };
+
+    /// the bytes every test chunk counts as
+    const BLOCK_BYTES_EACH: usize = 100;
+    const MESH_BYTES_EACH: usize = 50;
+
+    /// the chunks saved to a ``LogStore``
+    #[derive(Default)]
+    struct Log {
+        unwritten: Vec<IVec3>,
+        written: Vec<IVec3>,
+    }
+
+    /// keeps saved chunks unwritten until a flush, like ``RegionStore`` does
+    struct LogStore(Arc<Mutex<Log>>);
+    impl ChunkStore for LogStore {
+        fn load(&mut self, _pos: IVec3) -> Option<BlockData> {
+            None
+        }
+        fn save(&mut self, pos: IVec3, _data: &BlockData) {
+            self.0.lock().unwrap().unwritten.push(pos);
+        }
+        fn start_flush(&mut self) -> usize {
+            let mut log = self.0.lock().unwrap();
+            let unwritten = mem::take(&mut log.unwritten);
+            log.written.extend(&unwritten);
+            unwritten.len()
+        }
+        fn unwritten_bytes(&self) -> usize {
+            self.0.lock().unwrap().unwritten.len() * BLOCK_BYTES_EACH
+        }
+    }
+
+    /// an app at frame 10 with a chunk last visible at each of ``frames``, the ``n``th of them at ``(n, 0, 0)``
+    fn app(frames: &[u32], log: &Arc<Mutex<Log>>) -> (App, Vec<Entity>) {
+        let mut app = App::new();
+        for diagnostic in diagnostics() {
+            app.register_diagnostic(diagnostic);
+        }
+        app.insert_resource(FrameCount(10))
+            .insert_resource(WorldGen(Arc::new(FlatGenerator::default())))
+            .insert_resource(SaveStore(Box::new(LogStore(Arc::clone(log)))))
+            .init_resource::<ChunkManager>()
+            .init_resource::<DirtyChunks>();
+        let frames = frames.to_vec();
+        let chunks = app.world_mut()
+            .run_system_once(move |mut commands: Commands, mut chunk_manager: ResMut<ChunkManager>, mut dirty: ResMut<DirtyChunks>| {
+                frames.iter().zip(0..)
+                    .map(|(frame, x)| {
+                        let pos = IVec3::new(x, 0, 0);
+                        let chunk = Chunk { data: BlockData::new(ChunkData::filled(BlockID::STONE)), pos };
+                        let entity = chunk_manager.add_chunk(&mut commands, &mut dirty, chunk);
+                        commands.entity(entity).insert((
+                            LastVisible(*frame),
+                            ChunkMemory { block_bytes: BLOCK_BYTES_EACH, mesh_bytes: MESH_BYTES_EACH },
+                            VoxelMesh::new(pos),
+                        ));
+                        entity
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap();
+        (app, chunks)
+    }
+
+    fn enforce(app: &mut App, block_bytes: usize, mesh_bytes: usize) {
+        app.insert_resource(MemoryBudget { block_bytes, mesh_bytes, cache_bytes: usize::MAX });
+        app.world_mut().run_system_once(enforce_memory_budget).unwrap();
+    }
+
+    #[test]
+    fn the_least_recently_visible_chunks_are_evicted_first() {
+        let log = Arc::new(Mutex::new(Log::default()));
+        // the chunk last visible at frame 10 is visible this frame
+        let (mut app, chunks) = app(&[3, 1, 10, 2], &log);
+        let [at_3, at_1, visible, at_2] = chunks[..] else { unreachable!() };
+
+        // 200 bytes of meshes, so the two seen longest ago lose theirs
+        enforce(&mut app, usize::MAX, 120);
+        let world = app.world();
+        for entity in [at_1, at_2] {
+            assert!(world.get::<VoxelMesh>(entity).is_none());
+            assert!(world.get::<MeshEvicted>(entity).is_some());
+        }
+        for entity in [at_3, visible] {
+            assert!(world.get::<VoxelMesh>(entity).is_some());
+        }
+
+        // 400 bytes of blocks, so the two seen longest ago are unloaded, and the modified one is written
+        app.world_mut().entity_mut(at_2).insert(Modified);
+        enforce(&mut app, 250, usize::MAX);
+        let world = app.world();
+        assert!(world.get_entity(at_1).is_err());
+        assert!(world.get_entity(at_2).is_err());
+        assert!(world.get_entity(at_3).is_ok());
+        let chunk_manager = world.resource::<ChunkManager>();
+        assert!(!chunk_manager.map.contains_key(&IVec3::X));
+        assert!(!chunk_manager.map.contains_key(&(IVec3::X * 3)));
+        let written = mem::take(&mut log.lock().unwrap().written);
+        assert_eq!(written, [IVec3::X * 3]);
+
+        // the visible chunk stays, however small the budget
+        enforce(&mut app, 0, 0);
+        assert!(app.world().get_entity(at_3).is_err());
+        assert!(app.world().get_entity(visible).is_ok());
+    }
+
+    #[test]
+    fn unwritten_saves_count_against_the_budget() {
+        let log = Arc::new(Mutex::new(Log::default()));
+        // saved without being written, as when autosaves are off
+        log.lock().unwrap().unwritten = vec![IVec3::NEG_ONE; 3];
+        let (mut app, chunks) = app(&[10, 10], &log);
+        // the loaded chunks alone fit, but not with the unwritten ones
+        enforce(&mut app, 400, usize::MAX);
+        let log = log.lock().unwrap();
+        assert!(log.unwritten.is_empty());
+        assert_eq!(log.written.len(), 3);
+        assert!(chunks.iter().all(|entity| app.world().get_entity(*entity).is_ok()));
+    }
+}
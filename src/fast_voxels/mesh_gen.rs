@@ -1,8 +1,16 @@
-use bevy::math::{IVec3, UVec3};
+use bevy::{
+    math::{IVec3, UVec2, UVec3},
+    platform::collections::{HashMap, HashSet},
+};
 use strum::IntoEnumIterator;
 
 use crate::fast_voxels::{
-    base_types::{CHUNKSIZE, Chunk, DIRECTION_VECS, Direction, FAST_CHUNKSIZE, FastChunk, Quad, VoxelMesh}, blocks::BlockID
+    base_types::{CHUNKSIZE, DIRECTION_VECS, Direction, FAST_CHUNKSIZE, Quad, VoxelMesh},
+    block_registry::{BlockRegistry, Phase},
+    blocks::{BlockID, BlockState, GPUBlockID},
+    chunk_storage::{ChunkStorage, PalettedStorage},
+    neighborhood::ChunkNeighborhood,
+    world_settings::MeshingBackend,
 };
 
 pub struct ChunkBitMaskRow {
@@ -10,23 +18,32 @@ pub struct ChunkBitMaskRow {
 }
 /// a mask for a specific block for a slice. so it is either the block
 /// it masks for, or not the block it masks for.
-/// 
-/// each bit
+///
+/// each bit is one face, bit u of row v
+#[derive(Default)]
 pub struct ChunkBitMaskSlice {
     pub data: [u32; 32],
 }
 impl ChunkBitMaskSlice {
-    fn process(&self) -> Vec<(u32,u32)> {
-        for row in self.data {
-            let mut count_shift: u32 = 0;
-            let mut row_copy: u32 = row;
-            
-            while row_copy != 0  || count_shift >= 32 {
-                count_shift += row.leading_zeros();
-                row_copy <<= row.leading_zeros();
+    /// splits the set bits into rectangles, each as wide as it can be and then as tall,
+    /// and returns the corner and size of each. the slice is empty afterwards
+    fn process(&mut self) -> Vec<(UVec2, UVec2)> {
+        let mut rectangles = Vec::new();
+        for v in 0..self.data.len() {
+            while self.data[v] != 0 {
+                let u = self.data[v].trailing_zeros();
+                let width = (self.data[v] >> u).trailing_ones();
+                let row = u32::MAX >> (32 - width) << u;
+                let mut height = 1;
+                while v + height < self.data.len() && self.data[v + height] & row == row {
+                    self.data[v + height] &= !row;
+                    height += 1;
+                }
+                self.data[v] &= !row;
+                rectangles.push((UVec2::new(u, v as u32), UVec2::new(width, height as u32)));
             }
         }
-        todo!()
+        rectangles
     }
 }
 pub struct ChunkBitMask {
@@ -39,37 +56,174 @@ impl ChunkBitMask {
         }
     }
 }
+
+/// a chunk at a lower level of detail, where each cell of ``scale``³ voxels is one block.
+/// the cells around the chunk that share a face with it are included, so faces can be culled
+struct LodCells {
+    scale: i32,
+    /// how many cells the chunk is wide
+    cells: i32,
+    /// indexed like a chunk, with one cell of padding on every side
+    blocks: Vec<BlockID>,
+}
+impl LodCells {
+    /// the cells of the centre of ``neighborhood`` at level of detail ``lod``,
+    /// or ``None`` if that is full detail
+    fn new(neighborhood: &ChunkNeighborhood, registry: &BlockRegistry, lod: u32) -> Option<Self> {
+        let scale = 1 << lod.min(CHUNKSIZE.trailing_zeros());
+        if scale == 1 {
+            return None;
+        }
+        let cells = CHUNKSIZE as i32 / scale;
+        let padded = (cells + 2) as usize;
+        let mut blocks = vec![BlockID::AIR; padded * padded * padded];
+        let mut counts: Vec<(BlockID, i32)> = Vec::new();
+        for x in -1..=cells {
+            for y in -1..=cells {
+                for z in -1..=cells {
+                    let cell = IVec3::new(x, y, z);
+                    // the cells on the edges and corners never share a face with the chunk
+                    if cell.cmplt(IVec3::ZERO).bitmask().count_ones() + cell.cmpge(IVec3::splat(cells)).bitmask().count_ones() > 1 {
+                        continue;
+                    }
+                    counts.clear();
+                    for index in 0..scale * scale * scale {
+                        let offset = IVec3::new(index / (scale * scale), index / scale % scale, index % scale);
+                        let block = neighborhood.get(cell * scale + offset);
+                        if !registry.is_visible(block) { continue; }
+                        match counts.iter_mut().find(|(other, _)| *other == block) {
+                            Some((_, count)) => *count += 1,
+                            None => counts.push((block, 1)),
+                        }
+                    }
+                    // a cell is only filled when at least half of it is
+                    let visible: i32 = counts.iter().map(|(_, count)| count).sum();
+                    if visible * 2 < scale * scale * scale { continue; }
+                    if let Some((block, _)) = counts.iter().max_by_key(|(_, count)| *count) {
+                        let index = ((x + 1) as usize * padded + (y + 1) as usize) * padded + (z + 1) as usize;
+                        blocks[index] = *block;
+                    }
+                }
+            }
+        }
+        Some(Self { scale, cells, blocks })
+    }
+    /// the block of the cell at ``cell``, which can be one cell outside of the chunk
+    fn get(&self, cell: IVec3) -> BlockID {
+        let padded = (self.cells + 2) as usize;
+        let index = ((cell.x + 1) as usize * padded + (cell.y + 1) as usize) * padded + (cell.z + 1) as usize;
+        self.blocks[index]
+    }
+    /// the block of the cell containing the voxel at ``pos``
+    fn block_at(&self, pos: IVec3) -> BlockID {
+        self.get(pos.div_euclid(IVec3::splat(self.scale)))
+    }
+}
+
 impl VoxelMesh {
-    pub fn gen_greedy_mesh(chunk_pos: IVec3, data: &FastChunk) -> Self {
-        let mut return_val = VoxelMesh::new(chunk_pos);
-        for block in BlockID::iter() {
-            // get mask for each block
-            let mask: ChunkBitMask = ChunkBitMask::new();
-            for row in mask.data {
-                let mask = row ^ (row << 1);
-                
+    /// meshes the centre of ``neighborhood`` with ``meshing``, at level of detail ``lod``.
+    /// each level makes the voxels twice as large along every axis, 0 is full detail
+    pub fn gen_mesh(neighborhood: &ChunkNeighborhood, registry: &BlockRegistry, meshing: MeshingBackend, lod: u32) -> Self {
+        match meshing {
+            MeshingBackend::Naive => match LodCells::new(neighborhood, registry, lod) {
+                Some(cells) => VoxelMesh::gen_lod_mesh(neighborhood.pos, registry, &cells),
+                None => VoxelMesh::gen_detailed_mesh(neighborhood, registry),
+            },
+            MeshingBackend::Greedy => VoxelMesh::gen_greedy_mesh(neighborhood, registry, lod),
+        }
+    }
+    /// merges the faces of the mesh ``gen_mesh`` makes into as few quads as it can
+    pub fn gen_greedy_mesh(neighborhood: &ChunkNeighborhood, registry: &BlockRegistry, lod: u32) -> Self {
+        match LodCells::new(neighborhood, registry, lod) {
+            Some(cells) => {
+                let mut return_val = VoxelMesh::gen_lod_mesh(neighborhood.pos, registry, &cells);
+                return_val.merge_faces(|pos| registry.gpu_id(cells.block_at(pos)));
+                return_val
+            }
+            None => {
+                let mut return_val = VoxelMesh::gen_detailed_mesh(neighborhood, registry);
+                let data = neighborhood.center();
+                return_val.merge_faces(|pos| registry.gpu_id(data.get(pos)));
+                return_val
+            }
+        }
+    }
+    /// merges quads that lie next to each other in the same layer, and have the same look,
+    /// height and size, into larger ones. ``look_at`` is the ``GPUBlockID`` of a voxel, so
+    /// blocks that look the same are merged too
+    fn merge_faces(&mut self, look_at: impl Fn(IVec3) -> Option<GPUBlockID>) {
+        for (direction, quads) in Direction::iter().zip(&mut self.quads) {
+            let normal = DIRECTION_VECS[direction as usize].abs();
+            let (u, v) = direction.axes();
+            let mut slices: HashMap<(i32, Option<GPUBlockID>, u8, UVec2), ChunkBitMaskSlice> = HashMap::new();
+            for quad in quads.drain(..) {
+                let pos = quad.pos.as_ivec3();
+                let key = (pos.dot(normal), look_at(pos), quad.height, quad.size);
+                let cell = UVec2::new(pos.dot(u) as u32, pos.dot(v) as u32) / quad.size;
+                slices.entry(key).or_default().data[cell.y as usize] |= 1 << cell.x;
+            }
+            for ((layer, _, height, size), mut slice) in slices {
+                for (corner, extent) in slice.process() {
+                    let corner = corner * size;
+                    let pos = normal * layer + u * corner.x as i32 + v * corner.y as i32;
+                    quads.push(Quad { pos: pos.as_uvec3(), height, size: extent * size });
+                }
             }
         }
-        return_val
     }
-    pub fn gen_mesh(chunk_pos: IVec3, data: [[[&Chunk; 3]; 3]; 3]) -> Self {
+    /// one quad for every visible face of every cell of ``cells``. liquids are meshed full
+    fn gen_lod_mesh(chunk_pos: IVec3, registry: &BlockRegistry, cells: &LodCells) -> Self {
         let mut return_val: VoxelMesh = VoxelMesh::new(chunk_pos);
+        let size = UVec2::splat(cells.scale as u32);
+        for x in 0..cells.cells {
+            for y in 0..cells.cells {
+                for z in 0..cells.cells {
+                    let cell = IVec3::new(x, y, z);
+                    let block = cells.get(cell);
+                    if !registry.is_visible(block) { continue; }
+                    for i in Direction::iter() {
+                        let direction = DIRECTION_VECS[i as usize];
+                        if !registry.face_visible(block, cells.get(cell + direction)) { continue; }
+                        // the face lies on the voxels of the cell that are furthest towards it
+                        let pos = cell * cells.scale + direction.max(IVec3::ZERO) * (cells.scale - 1);
+                        return_val.quads[i as usize].push(Quad { size, ..Quad::new(pos.as_uvec3()) });
+                    }
+                }
+            }
+        }
+        return_val
+    }
+    /// one quad for every visible voxel face
+    fn gen_detailed_mesh(neighborhood: &ChunkNeighborhood, registry: &BlockRegistry) -> Self {
+        let data = neighborhood.center();
+        match data.blocks.uniform() {
+            Some(block) if !registry.is_visible(block) => return VoxelMesh::new(neighborhood.pos),
+            Some(block) if data.states.is_empty() => return VoxelMesh::gen_uniform_mesh(neighborhood, registry, block),
+            _ => {}
+        }
+        let mut return_val = match &data.blocks {
+            ChunkStorage::Paletted(paletted) => VoxelMesh::gen_paletted_mesh(neighborhood, registry, paletted),
+            _ => VoxelMesh::gen_naive_mesh(neighborhood, registry),
+        };
+        return_val.apply_fluid_levels(neighborhood, registry);
+        return_val
+    }
+    /// checks every face of every voxel
+    fn gen_naive_mesh(neighborhood: &ChunkNeighborhood, registry: &BlockRegistry) -> Self {
+        let data = neighborhood.center();
+        let mut return_val: VoxelMesh = VoxelMesh::new(neighborhood.pos);
         for x in 0..CHUNKSIZE as u32 {
             for y in 0..CHUNKSIZE as u32 {
                 for z in 0..CHUNKSIZE as u32 {
-                    let current_block = data[1][1][1].data[x as usize][y as usize][z as usize];
-                    if current_block == BlockID::Air {continue;}
                     let block_index = IVec3::new(
                         x as i32,
                         y as i32,
                         z as i32,
                     );
+                    let current_block = data.get(block_index);
+                    if !registry.is_visible(current_block) {continue;}
                     for i in Direction::iter() {
-                        if VoxelMesh::get_block(
-                            data,
-                            block_index + DIRECTION_VECS[i as usize])
-                            != BlockID::Air
-                        {
+                        if registry.face_visible(current_block, neighborhood.get(block_index + DIRECTION_VECS[i as usize])) {
                             return_val.quads[i as usize].push(
                                 Quad::new(UVec3::new(
                                     x as u32,
@@ -85,4 +239,295 @@ impl VoxelMesh {
 
         return_val
     }
-}
\ No newline at end of file
+    /// lowers the quads of liquids that arent full. their top face is always kept,
+    /// since the block above cant hide a surface that is below it
+    fn apply_fluid_levels(&mut self, neighborhood: &ChunkNeighborhood, registry: &BlockRegistry) {
+        let data = neighborhood.center();
+        let levels: HashMap<usize, u8> = data.states.iter()
+            .filter(|(index, _)| {
+                let block = data.blocks.get(ChunkStorage::position(*index));
+                registry.is_visible(block) && registry.properties(block).phase == Phase::Liquid
+            })
+            .map(|(index, state)| (index, state.fluid_level()))
+            .collect();
+        if levels.is_empty() { return; }
+
+        let mut surfaces: HashSet<usize> = levels.keys().copied().collect();
+        for (i, quads) in self.quads.iter_mut().enumerate() {
+            for quad in quads {
+                let index = ChunkStorage::index(quad.pos.as_ivec3());
+                if let Some(level) = levels.get(&index) {
+                    quad.height = *level;
+                    if i == Direction::Top as usize {
+                        surfaces.remove(&index);
+                    }
+                }
+            }
+        }
+        for index in surfaces {
+            let level = levels[&index];
+            if level == BlockState::FLUID_LEVELS { continue; }
+            let mut quad = Quad::new(ChunkStorage::position(index).as_uvec3());
+            quad.height = level;
+            self.quads[Direction::Top as usize].push(quad);
+        }
+    }
+    /// culls faces with bitmasks built straight from the palette indices, so voxels
+    /// never have to be looked up through the palette.
+    /// every x, y column of the chunk is a u64 with bit z + 1 set when the voxel is opaque,
+    /// so the voxels just outside the chunk fit in bits 0 and CHUNKSIZE + 1.
+    /// transparent voxels get a second set of columns, and only their faces are checked
+    /// one by one, since they are culled against the same block
+    fn gen_paletted_mesh(neighborhood: &ChunkNeighborhood, registry: &BlockRegistry, paletted: &PalettedStorage) -> Self {
+        const PADDED: usize = CHUNKSIZE + 2;
+        const INTERIOR: u64 = ((1 << CHUNKSIZE) - 1) << 1;
+        let column = |x: i32, y: i32| (x + 1) as usize * PADDED + (y + 1) as usize;
+        let size = CHUNKSIZE as i32;
+
+        let visible: Vec<bool> = paletted.palette().iter().map(|block| registry.is_visible(*block)).collect();
+        let opaque: Vec<bool> = paletted.palette().iter().map(|block| registry.is_opaque(*block)).collect();
+        let mut solid = vec![0_u64; PADDED * PADDED];
+        let mut transparent = vec![0_u64; PADDED * PADDED];
+        for (voxel, index) in paletted.indices().enumerate() {
+            if !visible[index] { continue; }
+            let x = (voxel / (CHUNKSIZE * CHUNKSIZE)) as i32;
+            let y = (voxel / CHUNKSIZE % CHUNKSIZE) as i32;
+            let columns = if opaque[index] { &mut solid } else { &mut transparent };
+            columns[column(x, y)] |= 1 << (voxel % CHUNKSIZE + 1);
+        }
+        // the neighbouring voxels in front of and behind every column
+        for x in 0..size {
+            for y in 0..size {
+                let bits = &mut solid[column(x, y)];
+                *bits |= u64::from(registry.is_opaque(neighborhood.get(IVec3::new(x, y, -1))));
+                *bits |= u64::from(registry.is_opaque(neighborhood.get(IVec3::new(x, y, size)))) << (CHUNKSIZE + 1);
+            }
+        }
+        // the columns of the neighbouring chunks on the x and y sides
+        for a in 0..size {
+            for (x, y) in [(-1, a), (size, a), (a, -1), (a, size)] {
+                for z in 0..size {
+                    if registry.is_opaque(neighborhood.get(IVec3::new(x, y, z))) {
+                        solid[column(x, y)] |= 1 << (z + 1);
+                    }
+                }
+            }
+        }
+
+        let mut return_val: VoxelMesh = VoxelMesh::new(neighborhood.pos);
+        for x in 0..size {
+            for y in 0..size {
+                let bits = solid[column(x, y)];
+                let translucent = transparent[column(x, y)];
+                let voxels = (bits & INTERIOR) | translucent;
+                if voxels == 0 { continue; }
+                let faces: [u64; 6] = [
+                    voxels & !solid[column(x, y + 1)],
+                    voxels & !solid[column(x, y - 1)],
+                    voxels & !solid[column(x - 1, y)],
+                    voxels & !solid[column(x + 1, y)],
+                    voxels & !(bits >> 1),
+                    voxels & !(bits << 1),
+                ];
+                for i in Direction::iter() {
+                    let mut face = faces[i as usize];
+                    while face != 0 {
+                        let bit = face.trailing_zeros();
+                        face &= face - 1;
+                        let pos = IVec3::new(x, y, bit as i32 - 1);
+                        if translucent & (1 << bit) != 0
+                            && neighborhood.get(pos) == neighborhood.get(pos + DIRECTION_VECS[i as usize]) {
+                            continue;
+                        }
+                        return_val.quads[i as usize].push(Quad::new(pos.as_uvec3()));
+                    }
+                }
+            }
+        }
+        return_val
+    }
+    /// a chunk filled with one visible block can only have faces on its border,
+    /// so only the voxels on each side of the chunk are checked against the neighbour
+    fn gen_uniform_mesh(neighborhood: &ChunkNeighborhood, registry: &BlockRegistry, block: BlockID) -> Self {
+        let mut return_val: VoxelMesh = VoxelMesh::new(neighborhood.pos);
+        let last = CHUNKSIZE as i32 - 1;
+        for i in Direction::iter() {
+            let direction = DIRECTION_VECS[i as usize];
+            let neighbor = neighborhood.chunk(direction).and_then(|data| data.uniform());
+            if neighbor.is_some_and(|neighbor| !registry.face_visible(block, neighbor)) { continue; }
+
+            for u in 0..CHUNKSIZE as i32 {
+                for v in 0..CHUNKSIZE as i32 {
+                    let block_index = match i {
+                        Direction::Top => IVec3::new(u, last, v),
+                        Direction::Bottom => IVec3::new(u, 0, v),
+                        Direction::Left => IVec3::new(0, u, v),
+                        Direction::Right => IVec3::new(last, u, v),
+                        Direction::Front => IVec3::new(u, v, last),
+                        Direction::Back => IVec3::new(u, v, 0),
+                    };
+                    if registry.face_visible(block, neighborhood.get(block_index + direction)) {
+                        return_val.quads[i as usize].push(Quad::new(block_index.as_uvec3()));
+                    }
+                }
+            }
+        }
+        return_val
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::{array::from_fn, sync::Arc};
+
+    use super::*;
+    use crate::fast_voxels::{
+        base_types::BlockData,
+        chunk_storage::{CHUNK_VOLUME, ChunkData},
+        neighborhood::MissingNeighbor,
+        world_gen::SplitMix,
+    };
+
+    /// air with ``count`` voxels set to random ones of ``blocks``
+    fn random_chunk(random: &mut SplitMix, blocks: &[BlockID], count: usize) -> ChunkData {
+        let mut data = ChunkData::filled(BlockID::AIR);
+        for _ in 0..count {
+            let pos = ChunkStorage::position((random.next_u64() % CHUNK_VOLUME as u64) as usize);
+            data.set(pos, blocks[(random.next_u64() % blocks.len() as u64) as usize]);
+        }
+        data
+    }
+
+    /// the faces of each direction, sorted
+    fn faces(mesh: &VoxelMesh) -> Vec<Vec<(UVec3, u8)>> {
+        mesh.quads.iter()
+            .map(|quads| {
+                let mut faces: Vec<_> = quads.iter().map(|quad| (quad.pos, quad.height)).collect();
+                faces.sort_by_key(|(pos, height)| (pos.to_array(), *height));
+                faces
+            })
+            .collect()
+    }
+
+    #[test]
+    fn paletted_mesher_matches_naive() {
+        let registry = BlockRegistry::default();
+        let blocks: Vec<BlockID> = registry.iter().map(|block| block.id).collect();
+        for seed in 0..9 {
+            let mut random = SplitMix(seed);
+            let count = [200, 4000, 40_000][seed as usize % 3];
+            // some neighbours missing, so the borders are checked against air too
+            let chunks = from_fn(|index| {
+                (index == 13 || random.next_u64() % 4 != 0).then(|| Arc::new(random_chunk(&mut random, &blocks, count)))
+            });
+            let neighborhood = ChunkNeighborhood::new(IVec3::ZERO, chunks, MissingNeighbor::Air).unwrap();
+            let ChunkStorage::Paletted(paletted) = &neighborhood.center().blocks else {
+                panic!("seed {seed}: the chunk isnt paletted");
+            };
+            let paletted = VoxelMesh::gen_paletted_mesh(&neighborhood, &registry, paletted);
+            let naive = VoxelMesh::gen_naive_mesh(&neighborhood, &registry);
+            assert!(faces(&paletted) == faces(&naive), "seed {seed}: the meshers disagree");
+        }
+    }
+
+    /// the faces of each direction, sorted, with merged quads split back into one face per voxel
+    fn unit_faces(mesh: &VoxelMesh) -> Vec<Vec<(UVec3, u8)>> {
+        let mut split = VoxelMesh::new(mesh.chunk_pos);
+        for ((direction, quads), split) in Direction::iter().zip(&mesh.quads).zip(&mut split.quads) {
+            let (u, v) = direction.axes();
+            for quad in quads {
+                for a in 0..quad.size.x as i32 {
+                    for b in 0..quad.size.y as i32 {
+                        let pos = quad.pos.as_ivec3() + u * a + v * b;
+                        split.push(Quad { height: quad.height, ..Quad::new(pos.as_uvec3()) });
+                    }
+                }
+            }
+        }
+        faces(&split)
+    }
+
+    #[test]
+    fn slices_split_into_rectangles() {
+        let mut full = ChunkBitMaskSlice { data: [u32::MAX; 32] };
+        assert_eq!(full.process(), [(UVec2::ZERO, UVec2::splat(32))]);
+        assert_eq!(full.data, [0; 32]);
+
+        let mut checkers = ChunkBitMaskSlice { data: from_fn(|v| 0x5555_5555 << (v % 2)) };
+        assert_eq!(checkers.process().len(), 32 * 16);
+
+        // an L: the foot is as wide as it can be, the rest of the column is left over
+        let mut l = ChunkBitMaskSlice::default();
+        l.data[..4].copy_from_slice(&[0b111, 0b1, 0b1, 0b1]);
+        assert_eq!(l.process(), [(UVec2::ZERO, UVec2::new(3, 1)), (UVec2::new(0, 1), UVec2::new(1, 3))]);
+    }
+
+    #[test]
+    fn greedy_meshes_cover_the_same_faces() {
+        let registry = BlockRegistry::default();
+        let blocks: Vec<BlockID> = registry.iter().map(|block| block.id).collect();
+        for seed in 0..6 {
+            let mut random = SplitMix(seed);
+            let count = [200, 4000, 40_000][seed as usize % 3];
+            let chunks = from_fn(|index| {
+                (index == 13 || random.next_u64() % 4 != 0).then(|| {
+                    let mut data = random_chunk(&mut random, &blocks, count);
+                    // some liquids that arent full, which are only merged with ones as high
+                    for _ in 0..count / 10 {
+                        let pos = ChunkStorage::position((random.next_u64() % CHUNK_VOLUME as u64) as usize);
+                        data.set_with_state(pos, BlockID::WATER, BlockState::fluid((random.next_u64() % 3) as u8 + 6));
+                    }
+                    Arc::new(data)
+                })
+            });
+            let neighborhood = ChunkNeighborhood::new(IVec3::ZERO, chunks, MissingNeighbor::Air).unwrap();
+            let detailed = VoxelMesh::gen_detailed_mesh(&neighborhood, &registry);
+            let greedy = VoxelMesh::gen_mesh(&neighborhood, &registry, MeshingBackend::Greedy, 0);
+            assert!(unit_faces(&greedy) == faces(&detailed), "seed {seed}: the greedy mesh covers other faces");
+            let count = |mesh: &VoxelMesh| mesh.quads.iter().map(Vec::len).sum::<usize>();
+            assert!(count(&greedy) <= count(&detailed), "seed {seed}: the greedy mesh has more quads");
+        }
+
+        // a chunk of stone surrounded by air is a cube
+        let mut chunks: [Option<BlockData>; 27] = from_fn(|_| None);
+        chunks[13] = Some(Arc::new(ChunkData::filled(BlockID::STONE)));
+        let neighborhood = ChunkNeighborhood::new(IVec3::ZERO, chunks, MissingNeighbor::Air).unwrap();
+        let cube = VoxelMesh::gen_mesh(&neighborhood, &registry, MeshingBackend::Greedy, 0);
+        assert!(cube.quads.iter().all(|quads| quads.len() == 1 && quads[0].size == UVec2::splat(32)));
+    }
+
+    #[test]
+    fn lower_levels_of_detail_mesh_larger_cells() {
+        let registry = BlockRegistry::default();
+        // stone up to half the chunk, with a few voxels of ground on it, under air
+        let mut data = ChunkData::filled(BlockID::AIR);
+        for index in 0..CHUNK_VOLUME {
+            let pos = ChunkStorage::position(index);
+            if pos.y < 16 {
+                data.set(pos, BlockID::STONE);
+            } else if pos.y == 16 && pos.x % 4 == 0 {
+                data.set(pos, BlockID::GROUND);
+            }
+        }
+        let mut chunks: [Option<BlockData>; 27] = from_fn(|_| None);
+        chunks[13] = Some(Arc::new(data));
+        let neighborhood = ChunkNeighborhood::new(IVec3::ZERO, chunks, MissingNeighbor::Air).unwrap();
+
+        // the ground fills a quarter of the cells it is in, which isnt enough to keep them
+        let naive = VoxelMesh::gen_mesh(&neighborhood, &registry, MeshingBackend::Naive, 1);
+        let top = &naive.quads[Direction::Top as usize];
+        assert_eq!(top.len(), 16 * 16);
+        assert!(top.iter().all(|quad| quad.pos.y == 15 && quad.size == UVec2::splat(2)));
+        assert!(naive.quads[Direction::Bottom as usize].iter().all(|quad| quad.pos.y == 0));
+
+        let greedy = VoxelMesh::gen_mesh(&neighborhood, &registry, MeshingBackend::Greedy, 1);
+        assert_eq!(unit_faces(&greedy), unit_faces(&naive));
+        let top = &greedy.quads[Direction::Top as usize];
+        assert_eq!(top.len(), 1);
+        assert_eq!((top[0].pos, top[0].size), (UVec3::new(0, 15, 0), UVec2::splat(32)));
+
+        // the whole chunk is one cell, which is half full, so stone. levels past that look the same
+        let coarsest = VoxelMesh::gen_mesh(&neighborhood, &registry, MeshingBackend::Naive, 5);
+        assert!(coarsest.quads.iter().all(|quads| quads.len() == 1 && quads[0].size == UVec2::splat(32)));
+        assert_eq!(unit_faces(&VoxelMesh::gen_mesh(&neighborhood, &registry, MeshingBackend::Naive, 9)), unit_faces(&coarsest));
+    }
+}
@@ -3,4 +3,22 @@ pub mod voxel_pipeline;
 pub mod mesh_gen;
 pub mod blocks;
 pub mod greedy_quad;
-pub mod voxel_plugin;
\ No newline at end of file
+pub mod voxel_plugin;
+pub mod neighborhood;
+pub mod chunk_manager;
+pub mod chunk_store;
+pub mod chunk_storage;
+pub mod memory_budget;
+pub mod chunk_plugin;
+pub mod world_settings;
+pub mod block_registry;
+pub mod fast_chunk;
+pub mod region_store;
+pub mod save_format;
+pub mod world_gen;
+pub mod ores;
+pub mod decoration;
+pub mod biomes;
+pub mod pipeline;
+pub mod structures;
+pub mod heightmap;
@@ -0,0 +1,178 @@
+use std::array::from_fn;
+
+use bevy::math::IVec3;
+use serde::{Deserialize, Serialize};
+
+use crate::fast_voxels::{
+    base_types::{BlockData, CHUNKSIZE},
+    blocks::{BlockID, BlockState},
+};
+
+/// decides what a ``ChunkNeighborhood`` does when one of the 26 neighbours
+/// of the centre chunk isnt loaded. meshing uses ``WorldSettings::missing_neighbors``
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MissingNeighbor {
+    /// treat the missing chunk as all air, so faces on that border are kept
+    #[default]
+    Air,
+    /// treat the missing chunk as solid, so faces on that border are culled
+    Solid,
+    /// dont build the neighbourhood at all, meshing has to wait for the neighbour.
+    /// chunks at the edge of the loaded area are never meshed
+    Defer,
+}
+
+/// a chunk together with its 26 neighbours, so block lookups can cross chunk borders
+/// without going through the world hashmap for every voxel.
+///
+/// offsets passed to ``get`` are relative to the centre chunk, and every axis must
+/// be in ``-CHUNKSIZE..2 * CHUNKSIZE``, which covers all 27 chunks.
+pub struct ChunkNeighborhood {
+    pub pos: IVec3,
+    center: BlockData,
+    /// x major, then y, then z. index 13 is the centre chunk
+    chunks: [Option<BlockData>; 27],
+    /// returned for voxels that lie in a missing neighbour
+    fallback: BlockID,
+}
+impl ChunkNeighborhood {
+    pub const MIN_OFFSET: i32 = -(CHUNKSIZE as i32);
+    pub const MAX_OFFSET: i32 = 2 * CHUNKSIZE as i32 - 1;
+    const CENTER: usize = 13;
+    const SHIFT: u32 = CHUNKSIZE.trailing_zeros();
+    const MASK: i32 = CHUNKSIZE as i32 - 1;
+
+    /// builds a neighbourhood from the centre chunk and its neighbours, indexed like
+    /// ``ChunkNeighborhood::chunk_offset``.
+    /// returns ``None`` if the centre chunk is missing, or if a neighbour is missing
+    /// and the policy is ``MissingNeighbor::Defer``.
+    pub fn new(pos: IVec3, chunks: [Option<BlockData>; 27], policy: MissingNeighbor) -> Option<Self> {
+        let center = chunks[Self::CENTER].clone()?;
+        let fallback = match policy {
+            MissingNeighbor::Air => BlockID::AIR,
+            MissingNeighbor::Solid => BlockID::STONE,
+            MissingNeighbor::Defer => {
+                if chunks.iter().any(Option::is_none) {
+                    return None;
+                }
+                BlockID::AIR
+            }
+        };
+        Some(Self {
+            pos,
+            center,
+            chunks,
+            fallback,
+        })
+    }
+    /// builds a neighbourhood by looking up each of the 27 chunks with ``lookup``
+    pub fn from_fn(
+        pos: IVec3,
+        policy: MissingNeighbor,
+        mut lookup: impl FnMut(IVec3) -> Option<BlockData>,
+    ) -> Option<Self> {
+        let chunks = from_fn(|index| lookup(pos + Self::chunk_offset(index)));
+        Self::new(pos, chunks, policy)
+    }
+    /// the chunk position offset of the chunk at ``index``, relative to the centre chunk
+    pub const fn chunk_offset(index: usize) -> IVec3 {
+        IVec3::new(
+            (index / 9) as i32 - 1,
+            (index / 3 % 3) as i32 - 1,
+            (index % 3) as i32 - 1,
+        )
+    }
+    /// the block data of the centre chunk
+    pub fn center(&self) -> &BlockData {
+        &self.center
+    }
+    /// the block data of the chunk at ``offset`` (each axis in ``-1..=1``), if it is loaded
+    pub fn chunk(&self, offset: IVec3) -> Option<&BlockData> {
+        debug_assert!(offset.abs().max_element() <= 1, "chunk offset {offset} is not a neighbour");
+        let index = (offset.x + 1) * 9 + (offset.y + 1) * 3 + (offset.z + 1);
+        self.chunks[index as usize].as_ref()
+    }
+    /// gets the block at ``offset``, relative to the minimum corner of the centre chunk
+    #[inline]
+    pub fn get(&self, offset: IVec3) -> BlockID {
+        match self.locate(offset) {
+            (Some(data), local) => data.get(local),
+            (None, _) => self.fallback,
+        }
+    }
+    /// gets the state of the voxel at ``offset``. voxels in missing neighbours are in the default state
+    pub fn state(&self, offset: IVec3) -> BlockState {
+        match self.locate(offset) {
+            (Some(data), local) => data.state(local),
+            (None, _) => BlockState::DEFAULT,
+        }
+    }
+    /// the chunk ``offset`` lies in, and the position inside of it
+    #[inline]
+    fn locate(&self, offset: IVec3) -> (Option<&BlockData>, IVec3) {
+        debug_assert!(
+            offset.min_element() >= Self::MIN_OFFSET && offset.max_element() <= Self::MAX_OFFSET,
+            "offset {offset} is outside of the neighbourhood",
+        );
+        let local = offset & Self::MASK;
+        let chunk = (offset >> Self::SHIFT) + 1;
+        if chunk == IVec3::ONE {
+            return (Some(&self.center), local);
+        }
+        let index = chunk.x * 9 + chunk.y * 3 + chunk.z;
+        (self.chunks[index as usize].as_ref(), local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::fast_voxels::chunk_storage::ChunkData;
+
+    /// every chunk filled with its own block, ``BlockID(index + 100)``
+    fn chunks() -> [Option<BlockData>; 27] {
+        from_fn(|index| Some(Arc::new(ChunkData::filled(BlockID(index as u16 + 100)))))
+    }
+
+    /// the voxels of the neighbour at ``offset`` that touch the centre chunk, and its far corner
+    fn voxels_in(offset: IVec3) -> [IVec3; 2] {
+        let size = CHUNKSIZE as i32;
+        let near = offset.map(|axis| match axis { -1 => -1, 0 => 5, _ => size });
+        let far = offset.map(|axis| match axis { -1 => -size, 0 => size - 1, _ => 2 * size - 1 });
+        [near, far]
+    }
+
+    #[test]
+    fn every_neighbour_is_found() {
+        let neighborhood = ChunkNeighborhood::new(IVec3::ZERO, chunks(), MissingNeighbor::Defer).unwrap();
+        for index in 0..27 {
+            let offset = ChunkNeighborhood::chunk_offset(index);
+            assert_eq!(neighborhood.chunk(offset).unwrap().get(IVec3::ZERO), BlockID(index as u16 + 100));
+            for voxel in voxels_in(offset) {
+                assert_eq!(neighborhood.get(voxel), BlockID(index as u16 + 100), "voxel {voxel} of neighbour {offset}");
+            }
+        }
+    }
+
+    #[test]
+    fn missing_neighbours_follow_the_policy() {
+        for index in (0..27).filter(|index| *index != ChunkNeighborhood::CENTER) {
+            let offset = ChunkNeighborhood::chunk_offset(index);
+            let mut missing = chunks();
+            missing[index] = None;
+            for (policy, block) in [(MissingNeighbor::Air, BlockID::AIR), (MissingNeighbor::Solid, BlockID::STONE)] {
+                let neighborhood = ChunkNeighborhood::new(IVec3::ZERO, missing.clone(), policy).unwrap();
+                for voxel in voxels_in(offset) {
+                    assert_eq!(neighborhood.get(voxel), block, "voxel {voxel} of missing neighbour {offset}");
+                    assert_eq!(neighborhood.state(voxel), BlockState::DEFAULT);
+                }
+            }
+            assert!(ChunkNeighborhood::new(IVec3::ZERO, missing, MissingNeighbor::Defer).is_none());
+        }
+        let mut no_center = chunks();
+        no_center[ChunkNeighborhood::CENTER] = None;
+        assert!(ChunkNeighborhood::new(IVec3::ZERO, no_center, MissingNeighbor::Air).is_none());
+    }
+}
@@ -0,0 +1,231 @@
+use std::{f32::consts::TAU, io, sync::Arc};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::fast_voxels::{
+    base_types::{BlockData, CHUNKSIZE},
+    block_registry::BlockRegistry,
+    blocks::BlockID,
+    chunk_storage::ChunkData,
+    pipeline::{Earlier, Stage, StagePass},
+    world_gen::{SplitMix, hash},
+};
+
+/// veins start at random points in cells of this many voxels
+pub const VEIN_CELL: i32 = 32;
+
+/// the shape of a ``VeinSettings`` deposit
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VeinShape {
+    /// a winding string of voxels
+    #[default]
+    Vein,
+    /// a flattened blob, like a pocket of oil
+    Pocket,
+}
+
+/// one kind of deposit, placed by ``OreGenerator``
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VeinSettings {
+    /// the name of the block the deposit is made of
+    pub block: String,
+    /// the name of the only block the deposit replaces
+    pub host: String,
+    /// the world heights deposits start in
+    pub min_y: i32,
+    pub max_y: i32,
+    /// about how many voxels a deposit has
+    pub size: u32,
+    /// how many deposits start in each ``VEIN_CELL``³ voxels, on average
+    pub frequency: f32,
+    #[serde(default)]
+    pub shape: VeinShape,
+}
+impl VeinSettings {
+    /// coal, copper and oil
+    pub fn defaults() -> Vec<Self> {
+        let vein = |block: &str, min_y, max_y, size, frequency, shape| Self {
+            block: block.to_string(),
+            host: "stone".to_string(),
+            min_y,
+            max_y,
+            size,
+            frequency,
+            shape,
+        };
+        vec![
+            vein("coal", -128, 40, 14, 6.0, VeinShape::Vein),
+            vein("copper", -256, 16, 8, 3.0, VeinShape::Vein),
+            vein("oil", -512, -32, 200, 0.15, VeinShape::Pocket),
+        ]
+    }
+    /// how far from where it starts a deposit can reach
+    fn reach(&self) -> i32 {
+        match self.shape {
+            VeinShape::Vein => self.size as i32,
+            VeinShape::Pocket => (pocket_radius(self.size) * POCKET_STRETCH.max_element()).ceil() as i32,
+        }
+    }
+}
+
+/// a ``VeinSettings`` with its block names looked up
+struct Vein {
+    settings: VeinSettings,
+    block: BlockID,
+    host: BlockID,
+    seed: u64,
+}
+
+/// places deposits of ores and oil into the rock, in the ``Stage::Ores`` stage.
+/// every deposit is placed by each chunk it might reach, starting from the same random
+/// numbers, so deposits across chunk borders come out whole whichever chunk is generated first
+pub struct OreGenerator {
+    veins: Vec<Vein>,
+}
+impl OreGenerator {
+    pub fn new(seed: u64, veins: &[VeinSettings], registry: &BlockRegistry) -> io::Result<Self> {
+        let block = |name: &str| registry.by_name(name).ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("deposits of block {name:?}, which isnt registered"),
+        ));
+        let veins = veins.iter().zip(0..)
+            .map(|(settings, index)| Ok(Vein {
+                settings: settings.clone(),
+                block: block(&settings.block)?,
+                host: block(&settings.host)?,
+                seed: hash(seed, 4, index),
+            }))
+            .collect::<io::Result<_>>()?;
+        Ok(Self { veins })
+    }
+    /// places every deposit of ``vein`` that reaches the chunk at ``chunk_pos``
+    fn place(vein: &Vein, chunk_pos: IVec3, data: &mut ChunkData) {
+        let settings = &vein.settings;
+        let chunk_min = chunk_pos * CHUNKSIZE as i32;
+        let reach = settings.reach();
+        let min = (chunk_min - reach).max(IVec3::new(i32::MIN, settings.min_y, i32::MIN)).div_euclid(IVec3::splat(VEIN_CELL));
+        let max = (chunk_min + CHUNKSIZE as i32 + reach).min(IVec3::new(i32::MAX, settings.max_y, i32::MAX)).div_euclid(IVec3::splat(VEIN_CELL));
+        let mut place = |pos: IVec3| {
+            let local = pos - chunk_min;
+            if local.cmpge(IVec3::ZERO).all() && local.cmplt(IVec3::splat(CHUNKSIZE as i32)).all()
+                && data.get(local) == vein.host
+            {
+                data.set(local, vein.block);
+            }
+        };
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let mut random = SplitMix(hash(hash(vein.seed, x, y), z, 0));
+                    let count = settings.frequency as u32 + u32::from(random.next_f32() < settings.frequency.fract());
+                    for _ in 0..count {
+                        let offset = Vec3::new(random.next_f32(), random.next_f32(), random.next_f32()) * VEIN_CELL as f32;
+                        let start = IVec3::new(x, y, z) * VEIN_CELL + offset.as_ivec3();
+                        // every deposit uses its own random numbers, so skipping one doesnt change the others
+                        let mut deposit = SplitMix(random.next_u64());
+                        if !(settings.min_y..=settings.max_y).contains(&start.y)
+                            || (start - chunk_min).min(chunk_min + CHUNKSIZE as i32 - 1 - start).min_element() < -reach
+                        {
+                            continue;
+                        }
+                        match settings.shape {
+                            VeinShape::Vein => {
+                                let mut pos = start;
+                                for _ in 0..settings.size {
+                                    place(pos);
+                                    let step = (deposit.next_u64() % 27) as i32;
+                                    pos += IVec3::new(step % 3, step / 3 % 3, step / 9) - IVec3::ONE;
+                                }
+                            }
+                            VeinShape::Pocket => {
+                                let radius = pocket_radius(settings.size);
+                                let (sin, cos) = (deposit.next_f32() * TAU).sin_cos();
+                                let extent = settings.reach();
+                                for dx in -extent..=extent {
+                                    for dy in -extent..=extent {
+                                        for dz in -extent..=extent {
+                                            let turned = Vec3::new(dx as f32 * cos - dz as f32 * sin, dy as f32, dx as f32 * sin + dz as f32 * cos);
+                                            if (turned / POCKET_STRETCH).length_squared() <= radius * radius {
+                                                place(start + IVec3::new(dx, dy, dz));
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+impl StagePass for OreGenerator {
+    fn stage(&self) -> Stage {
+        Stage::Ores
+    }
+    fn run(&self, chunk_pos: IVec3, data: &mut BlockData, _earlier: &Earlier) {
+        let data = Arc::make_mut(data);
+        let bottom = chunk_pos.y * CHUNKSIZE as i32;
+        for vein in &self.veins {
+            let reach = vein.settings.reach();
+            let in_range = bottom + CHUNKSIZE as i32 + reach > vein.settings.min_y
+                && bottom - reach <= vein.settings.max_y;
+            // a chunk without any of the host block cant have deposits
+            if in_range && data.blocks.uniform().is_none_or(|block| block == vein.host) {
+                Self::place(vein, chunk_pos, data);
+            }
+        }
+    }
+}
+
+/// pockets are wider than they are tall
+const POCKET_STRETCH: Vec3 = Vec3::new(1.5, 0.75, 1.5);
+
+/// the radius of a sphere of about ``size`` voxels
+fn pocket_radius(size: u32) -> f32 {
+    (size as f32 * 3.0 / (4.0 * std::f32::consts::PI)).cbrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fast_voxels::{
+        pipeline::GenerationPipeline,
+        region_store::diff_chunk,
+        world_gen::{FlatGenerator, WorldGenerator},
+    };
+
+    #[test]
+    fn deposits_across_chunk_borders_come_out_the_same_in_any_order() {
+        let registry = BlockRegistry::default();
+        let left = IVec3::ZERO;
+        let right = IVec3::X;
+        for (shape, size) in [(VeinShape::Vein, 30), (VeinShape::Pocket, 200)] {
+            let veins = [VeinSettings {
+                block: "coal".to_string(),
+                host: "stone".to_string(),
+                min_y: -64,
+                max_y: 64,
+                size,
+                frequency: 8.0,
+                shape,
+            }];
+            // solid stone far above the chunks
+            let pipeline = || GenerationPipeline::new(Arc::new(FlatGenerator { height: 1024, block: BlockID::STONE }))
+                .with_pass(Arc::new(OreGenerator::new(7, &veins, &registry).unwrap()));
+            let left_first = pipeline();
+            let (left_a, right_a) = (left_first.generate(left), left_first.generate(right));
+            let right_first = pipeline();
+            let (right_b, left_b) = (right_first.generate(right), right_first.generate(left));
+            assert!(diff_chunk(&left_a, &left_b).is_empty(), "{shape:?}");
+            assert!(diff_chunk(&right_a, &right_b).is_empty(), "{shape:?}");
+            // deposits reach the border from both sides
+            let coal = BlockID::COAL;
+            let edge = CHUNKSIZE as i32 - 1;
+            let mut border = (0..CHUNKSIZE as i32).flat_map(|y| (0..CHUNKSIZE as i32).map(move |z| (y, z)));
+            assert!(border.clone().any(|(y, z)| left_a.get(IVec3::new(edge, y, z)) == coal), "{shape:?}");
+            assert!(border.any(|(y, z)| right_a.get(IVec3::new(0, y, z)) == coal), "{shape:?}");
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use std::sync::{
+    Arc, Mutex, OnceLock, PoisonError,
+    atomic::{AtomicU64, Ordering},
+};
+
+use bevy::{platform::collections::HashMap, prelude::*};
+
+use crate::fast_voxels::{
+    base_types::BlockData,
+    chunk_storage::ChunkData,
+    memory_budget::ChunkMemory,
+    world_gen::WorldGenerator,
+};
+
+/// how many chunks each stage keeps the results of at most, so neighbours dont have to be made again.
+/// ``MemoryBudget::cache_bytes`` usually trims them to less
+pub const CACHE_CHUNKS: usize = 1024;
+
+/// the stages every chunk goes through, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    /// the shape of the land, made by a ``WorldGenerator``. passes of this stage reshape it
+    /// before anything else runs
+    Terrain,
+    /// caves and tunnels
+    Carve,
+    /// deposits in the rock
+    Ores,
+    /// trees, plants and anything else on the surface
+    Decorate,
+    /// the light of every voxel, see ``LightPass``
+    Light,
+}
+
+/// one step of generation after the ``WorldGenerator``. a pass only sees its own chunk and, within
+/// ``reach``, the chunks around it as they were after the stage before its own, so its result
+/// only depends on the position and never on which chunks were generated first
+pub trait StagePass: Send + Sync {
+    fn stage(&self) -> Stage;
+    /// how many chunks along each axis the neighbours ``run`` looks at can be away
+    fn reach(&self) -> IVec3 {
+        IVec3::ZERO
+    }
+    /// ``data`` is shared with the stage before until the pass changes it through ``Arc::make_mut``,
+    /// so passes that leave a chunk as it is dont copy it
+    fn run(&self, chunk_pos: IVec3, data: &mut BlockData, earlier: &Earlier);
+}
+
+/// lights the chunks. chunks dont store light yet, so it leaves them as they are, without copying
+/// them. it holds the place of the ``Stage::Light`` passes, so everything that changes blocks
+/// stays before it when light is added
+pub struct LightPass;
+impl StagePass for LightPass {
+    fn stage(&self) -> Stage {
+        Stage::Light
+    }
+    fn run(&self, _chunk_pos: IVec3, _data: &mut BlockData, _earlier: &Earlier) {}
+}
+
+/// the chunks around the one a ``StagePass`` runs on, after the stage before its own
+pub struct Earlier<'a> {
+    pipeline: &'a GenerationPipeline,
+    /// the level of the stage before, or none for the bare terrain
+    level: Option<usize>,
+    center: IVec3,
+    reach: IVec3,
+}
+impl Earlier<'_> {
+    /// the chunk at ``chunk_pos``, which has to be within the pass's ``reach``
+    pub fn get(&self, chunk_pos: IVec3) -> BlockData {
+        debug_assert!(
+            (chunk_pos - self.center).abs().cmple(self.reach).all(),
+            "chunk {chunk_pos} is out of the reach of a pass running on {}", self.center,
+        );
+        match self.level {
+            Some(level) => self.pipeline.output(level, chunk_pos),
+            // ``Stage::Terrain`` passes see the terrain before any of them ran, which isnt cached
+            None => self.pipeline.terrain.generate(chunk_pos),
+        }
+    }
+}
+
+/// the results of one stage, by chunk. a result is made by whichever thread asks for it first,
+/// and the others wait for it
+#[derive(Default)]
+struct StageCache {
+    chunks: Mutex<HashMap<IVec3, (Arc<OnceLock<BlockData>>, u64)>>,
+}
+impl StageCache {
+    fn get(&self, chunk_pos: IVec3, now: u64, make: impl FnOnce() -> BlockData) -> BlockData {
+        let cell = {
+            let mut chunks = self.chunks.lock().unwrap_or_else(PoisonError::into_inner);
+            if chunks.len() >= CACHE_CHUNKS && !chunks.contains_key(&chunk_pos) {
+                // forgets the half that was used longest ago. anyone still making one of them keeps it
+                let mut used: Vec<u64> = chunks.values().map(|(_, used)| *used).collect();
+                let (_, median, _) = used.select_nth_unstable(CACHE_CHUNKS / 2);
+                let median = *median;
+                chunks.retain(|_, (_, used)| *used > median);
+            }
+            let (cell, used) = chunks.entry(chunk_pos).or_default();
+            *used = now;
+            Arc::clone(cell)
+        };
+        cell.get_or_init(make).clone()
+    }
+    /// every result that is made, with when it was last used
+    fn results(&self) -> Vec<(u64, BlockData)> {
+        let chunks = self.chunks.lock().unwrap_or_else(PoisonError::into_inner);
+        chunks.values()
+            .filter_map(|(cell, used)| Some((*used, cell.get()?.clone())))
+            .collect()
+    }
+    /// forgets the results last used at or before ``used``
+    fn forget(&self, used: u64) {
+        let mut chunks = self.chunks.lock().unwrap_or_else(PoisonError::into_inner);
+        chunks.retain(|_, (_, last_used)| *last_used > used);
+    }
+}
+
+/// the passes of one stage, and their results
+struct StageRun {
+    stage: Stage,
+    passes: Vec<Arc<dyn StagePass>>,
+    cache: StageCache,
+}
+
+/// makes chunks stage by stage: the ``WorldGenerator`` first, then every ``StagePass`` by its ``Stage``.
+/// the results of every stage are cached, so passes that look at their neighbours dont make
+/// them again for every chunk around them
+pub struct GenerationPipeline {
+    terrain: Arc<dyn WorldGenerator>,
+    /// ``Stage::Terrain`` and the other stages that have passes, in order
+    stages: Vec<StageRun>,
+    /// counts uses of the caches, to tell which results were used longest ago
+    clock: AtomicU64,
+}
+impl GenerationPipeline {
+    pub fn new(terrain: Arc<dyn WorldGenerator>) -> Self {
+        Self {
+            terrain,
+            stages: vec![StageRun {
+                stage: Stage::Terrain,
+                passes: Vec::new(),
+                cache: StageCache::default(),
+            }],
+            clock: AtomicU64::new(0),
+        }
+    }
+    /// adds a pass, which runs after the ones added to its stage before it
+    pub fn with_pass(mut self, pass: Arc<dyn StagePass>) -> Self {
+        let stage = pass.stage();
+        let index = self.stages.iter().position(|run| run.stage >= stage).unwrap_or(self.stages.len());
+        match self.stages.get_mut(index) {
+            Some(run) if run.stage == stage => run.passes.push(pass),
+            _ => self.stages.insert(index, StageRun {
+                stage,
+                passes: vec![pass],
+                cache: StageCache::default(),
+            }),
+        }
+        self
+    }
+    /// when every cached result was last used, and its bytes. a result a stage shares
+    /// with the stage before counts once
+    fn sizes(&self) -> Vec<(u64, usize)> {
+        let mut sizes: HashMap<*const ChunkData, (u64, usize)> = HashMap::new();
+        for (used, data) in self.stages.iter().flat_map(|run| run.cache.results()) {
+            let size = sizes.entry(Arc::as_ptr(&data)).or_insert((used, ChunkMemory::of_blocks(&data)));
+            size.0 = size.0.max(used);
+        }
+        sizes.into_values().collect()
+    }
+    /// the chunk after ``level`` of the stages, where 0 is ``Stage::Terrain``
+    fn output(&self, level: usize, chunk_pos: IVec3) -> BlockData {
+        let now = self.clock.fetch_add(1, Ordering::Relaxed);
+        let run = &self.stages[level];
+        run.cache.get(chunk_pos, now, || {
+            let mut data = match level.checked_sub(1) {
+                Some(before) => self.output(before, chunk_pos),
+                None => self.terrain.generate(chunk_pos),
+            };
+            for pass in &run.passes {
+                let earlier = Earlier {
+                    pipeline: self,
+                    level: level.checked_sub(1),
+                    center: chunk_pos,
+                    reach: pass.reach(),
+                };
+                pass.run(chunk_pos, &mut data, &earlier);
+            }
+            data
+        })
+    }
+}
+impl WorldGenerator for GenerationPipeline {
+    fn generate(&self, chunk_pos: IVec3) -> BlockData {
+        self.output(self.stages.len() - 1, chunk_pos)
+    }
+    fn cache_bytes(&self) -> usize {
+        self.sizes().into_iter().map(|(_, bytes)| bytes).sum()
+    }
+    fn trim_cache(&self, max_bytes: usize) {
+        // every stage uses the same clock, so the newest results are kept across all of them
+        let mut sizes = self.sizes();
+        sizes.sort_unstable_by(|a, b| b.cmp(a));
+        let mut kept = 0;
+        let Some(&(newest_dropped, _)) = sizes.iter().find(|(_, bytes)| {
+            kept += bytes;
+            kept > max_bytes
+        }) else { return; };
+        for run in &self.stages {
+            run.cache.forget(newest_dropped);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, thread};
+
+    use super::*;
+    use crate::fast_voxels::{
+        block_registry::BlockRegistry,
+        blocks::BlockID,
+        decoration::TreeSettings,
+        ores::VeinSettings,
+        region_store::diff_chunk,
+        world_gen::{CaveSettings, FlatGenerator, GeneratorKind, SplitMix, TerrainSettings, hash},
+        world_settings::WorldSettings,
+    };
+
+    /// the chunks within ``radius`` chunks of the origin, in an order shuffled by ``seed``
+    fn shuffled(radius: i32, seed: u64) -> Vec<IVec3> {
+        let mut positions = Vec::new();
+        for x in -radius..=radius {
+            for y in -radius..=radius {
+                for z in -radius..=radius {
+                    positions.push(IVec3::new(x, y, z));
+                }
+            }
+        }
+        let mut random = SplitMix(hash(seed, 0, 0));
+        for index in (1..positions.len()).rev() {
+            positions.swap(index, (random.next_u64() % (index as u64 + 1)) as usize);
+        }
+        positions
+    }
+
+    /// a world with caves, the default deposits and trees, seeded with ``seed``.
+    /// the tests dont use ``WorldSettings::PATH``, so changing the game's settings doesnt change them
+    fn settings(seed: u64) -> WorldSettings {
+        WorldSettings {
+            generator: GeneratorKind::Caves {
+                terrain: TerrainSettings::default(),
+                caves: CaveSettings::default(),
+            },
+            ores: VeinSettings::defaults(),
+            trees: Some(TreeSettings::default()),
+            structures: Vec::new(),
+            seed,
+            ..default()
+        }
+    }
+
+    /// generates the same chunks with two fresh pipelines, in different orders and the second
+    /// time on several threads at once. every chunk has to come out the same both times
+    fn check_order(seed: u64, first_order: u64, second_order: u64) {
+        let settings = settings(seed);
+        let registry = BlockRegistry::default();
+        let first = settings.generator.create(&settings, &registry).unwrap();
+        let second = settings.generator.create(&settings, &registry).unwrap();
+
+        let expected: Vec<_> = shuffled(2, first_order).into_iter().map(|pos| (pos, first.generate(pos))).collect();
+        let positions = shuffled(2, second_order);
+        let generated: HashMap<_, _> = thread::scope(|scope| {
+            let second = &second;
+            let workers: Vec<_> = positions.chunks(positions.len().div_ceil(4))
+                .map(|positions| scope.spawn(move || positions.iter().map(|pos| (*pos, second.generate(*pos))).collect::<Vec<_>>()))
+                .collect();
+            workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect()
+        });
+        for (pos, data) in &expected {
+            let voxels = diff_chunk(&generated[pos], data).len();
+            assert_eq!(voxels, 0, "{voxels} voxels of chunk {pos} depend on the order, with seed {seed}");
+        }
+    }
+
+    #[test]
+    fn generation_doesnt_depend_on_order() {
+        check_order(0, 1, 2);
+        check_order(0x5eed, 3, 4);
+        check_order(u64::MAX, 5, 6);
+    }
+
+    #[test]
+    fn trimmed_caches_fit_the_budget() {
+        let settings = settings(7);
+        let generator = settings.generator.create(&settings, &BlockRegistry::default()).unwrap();
+        for pos in shuffled(2, 7) {
+            generator.generate(pos);
+        }
+        let bytes = generator.cache_bytes();
+        assert!(bytes > 0);
+        generator.trim_cache(bytes / 2);
+        assert!(generator.cache_bytes() <= bytes / 2);
+        // what was forgotten is made again the same way
+        let fresh = settings.generator.create(&settings, &BlockRegistry::default()).unwrap();
+        for pos in shuffled(1, 8) {
+            assert!(diff_chunk(&generator.generate(pos), &fresh.generate(pos)).is_empty());
+        }
+    }
+
+    /// sets the first voxel of every chunk, after checking what the stage before left in it
+    struct Stamp {
+        stage: Stage,
+        expected: BlockID,
+        block: BlockID,
+    }
+    impl StagePass for Stamp {
+        fn stage(&self) -> Stage {
+            self.stage
+        }
+        fn run(&self, _chunk_pos: IVec3, data: &mut BlockData, _earlier: &Earlier) {
+            assert_eq!(data.get(IVec3::ZERO), self.expected, "the {:?} pass ran out of order", self.stage);
+            Arc::make_mut(data).set(IVec3::ZERO, self.block);
+        }
+    }
+
+    #[test]
+    fn stages_run_in_order() {
+        let flat = FlatGenerator { height: 16, block: BlockID::STONE };
+        let pass = |stage, expected, block| Arc::new(Stamp { stage, expected, block });
+        // added out of order, and each pass checks the block the one before it set
+        let pipeline = GenerationPipeline::new(Arc::new(flat))
+            .with_pass(Arc::new(LightPass))
+            .with_pass(pass(Stage::Decorate, BlockID::COAL, BlockID::GROUND))
+            .with_pass(pass(Stage::Terrain, BlockID::STONE, BlockID::WOOD))
+            .with_pass(pass(Stage::Ores, BlockID::WOOD, BlockID::COAL));
+        assert_eq!(pipeline.generate(IVec3::ZERO).get(IVec3::ZERO), BlockID::GROUND);
+    }
+
+    #[test]
+    fn the_light_stage_keeps_chunks_as_they_are() {
+        let flat = FlatGenerator { height: 16, block: BlockID::STONE };
+        let decorate = Stamp { stage: Stage::Decorate, expected: BlockID::STONE, block: BlockID::WOOD };
+        let pipeline = GenerationPipeline::new(Arc::new(flat))
+            .with_pass(Arc::new(decorate))
+            .with_pass(Arc::new(LightPass));
+        let lit = pipeline.generate(IVec3::ZERO);
+        let decorated = pipeline.output(1, IVec3::ZERO);
+        assert_eq!(lit.get(IVec3::ZERO), BlockID::WOOD);
+        assert!(Arc::ptr_eq(&lit, &decorated), "the light stage copied the chunk");
+        // the chunk both stages share counts once
+        let terrain = pipeline.output(0, IVec3::ZERO);
+        assert_eq!(pipeline.cache_bytes(), ChunkMemory::of_blocks(&terrain) + ChunkMemory::of_blocks(&decorated));
+    }
+}
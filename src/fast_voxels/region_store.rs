@@ -0,0 +1,1131 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError, RwLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    platform::collections::HashMap,
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, IoTaskPool, Task, futures_lite::future},
+};
+use flate2::{Compression, read::DeflateDecoder, write::DeflateEncoder};
+
+use crate::fast_voxels::{
+    base_types::{BlockData, CHUNKSIZE},
+    block_registry::BlockRegistry,
+    blocks::{BlockID, BlockState},
+    chunk_storage::{CHUNK_VOLUME, ChunkData, ChunkStorage},
+    chunk_store::{ChunkStore, LoadedChunk, SaveStatus},
+    memory_budget::ChunkMemory,
+    save_format::{BlockMapping, WorldMeta},
+    world_gen::WorldGenerator,
+    world_settings::WorldSettings,
+};
+
+/// how many chunks a region file holds along each axis
+pub const REGION_SIZE: i32 = 32;
+const REGION_CHUNKS: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+const MAGIC: &[u8; 4] = b"VXRG";
+/// version 1 files have no checksums, but are still read
+const VERSION: u32 = 2;
+/// the magic and version every region file starts with
+const START_LEN: usize = 8;
+
+/// saves chunks to disk in region files of ``REGION_SIZE``³ chunks each.
+///
+/// a region file starts with a header holding the offset, length and checksum of every chunk
+/// in it, followed by the chunks, each compressed on its own. ``save`` only keeps a snapshot of
+/// the chunk, which is cheap because ``BlockData`` is shared. the snapshots are compressed and
+/// written by ``flush``, or by ``start_flush`` on the ``IoTaskPool`` so the frame doesnt wait.
+/// either rewrites each changed region to a temporary file and renames it over the old one,
+/// so a crash never leaves a half written region behind.
+///
+/// a chunk that fails its checksum or doesnt decode is moved to the ``quarantine`` folder of the
+/// world and generated again, and so is a region file whose header is unreadable.
+///
+/// chunks are saved with the world's own block ids from its ``WorldMeta``, and translated
+/// to and from the registry's ids on the way.
+///
+/// only chunks that differ from what the ``WorldGenerator`` makes are stored, and chunks with
+/// few edits only store the voxels that changed, so untouched terrain takes no space at all
+pub struct RegionStore {
+    shared: Arc<Shared>,
+    /// chunks saved since the last flush started
+    pending: RegionChunks,
+    /// the background flush, if one is running
+    flushing: Option<Flush>,
+}
+
+/// chunk snapshots by region and index. ``None`` removes a chunk from its region,
+/// so it is generated again
+type RegionChunks = HashMap<IVec3, HashMap<usize, Option<BlockData>>>;
+
+/// the parts of a ``RegionStore`` its background flushes use too
+struct Shared {
+    dir: PathBuf,
+    generator: Arc<dyn WorldGenerator>,
+    ids: Mutex<WorldIds>,
+}
+
+/// everything needed to translate block ids, which flushes add new world ids to
+struct WorldIds {
+    registry: BlockRegistry,
+    meta: WorldMeta,
+    mapping: BlockMapping,
+}
+impl WorldIds {
+    fn to_world(&mut self, block: BlockID) -> u16 {
+        self.mapping.to_world(block, &mut self.meta, &self.registry)
+    }
+}
+
+struct Flush {
+    /// returns the regions that couldnt be written
+    task: Task<Vec<IVec3>>,
+    /// what is being written, which loads have to see until it is on disk
+    chunks: Arc<RegionChunks>,
+    /// how many regions are written so far
+    done: Arc<AtomicUsize>,
+}
+
+impl RegionStore {
+    /// opens the world saved in ``dir``, upgrading its format if it is old.
+    /// an empty or missing folder starts a new world. ``generator`` has to be the one ``settings``
+    /// create, and a world generated with another seed or other generation settings is refused
+    pub fn open(
+        dir: impl Into<PathBuf>,
+        registry: &BlockRegistry,
+        settings: &WorldSettings,
+        generator: Arc<dyn WorldGenerator>,
+    ) -> io::Result<Self> {
+        let dir = dir.into();
+        let (meta, upgraded) = WorldMeta::open(&dir, registry, settings.seed, &settings.generation())?;
+        let mut mapping = BlockMapping::new(&meta, registry);
+        mapping.changed = upgraded;
+        Ok(Self {
+            shared: Arc::new(Shared {
+                dir,
+                generator,
+                ids: Mutex::new(WorldIds {
+                    registry: registry.clone(),
+                    meta,
+                    mapping,
+                }),
+            }),
+            pending: HashMap::new(),
+            flushing: None,
+        })
+    }
+    /// the region containing ``chunk_pos``, and the chunk's index inside of it
+    pub fn locate(chunk_pos: IVec3) -> (IVec3, usize) {
+        let region = chunk_pos.div_euclid(IVec3::splat(REGION_SIZE));
+        let local = chunk_pos.rem_euclid(IVec3::splat(REGION_SIZE));
+        let index = (local.x * REGION_SIZE + local.y) * REGION_SIZE + local.z;
+        (region, index as usize)
+    }
+    /// the position of the chunk at ``index`` in ``region``, the inverse of ``locate``
+    pub fn chunk_pos(region: IVec3, index: usize) -> IVec3 {
+        let index = index as i32;
+        let local = IVec3::new(index / (REGION_SIZE * REGION_SIZE), index / REGION_SIZE % REGION_SIZE, index % REGION_SIZE);
+        region * REGION_SIZE + local
+    }
+    pub fn region_path(&self, region: IVec3) -> PathBuf {
+        self.shared.region_path(region)
+    }
+    /// checks every chunk of every region file in the world without changing anything,
+    /// sorted by path
+    pub fn verify(&self) -> io::Result<Vec<RegionReport>> {
+        let mut paths = fs::read_dir(&self.shared.dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "region"));
+        paths.sort();
+        Ok(paths.into_iter().map(|path| self.shared.verify_region(path)).collect())
+    }
+    /// the newest snapshot of the chunk at ``pos`` that isnt on disk yet
+    fn unwritten(&self, pos: IVec3) -> Option<&Option<BlockData>> {
+        let (region, index) = Self::locate(pos);
+        let flushing = self.flushing.as_ref().map(|flush| &*flush.chunks);
+        [Some(&self.pending), flushing].into_iter().flatten()
+            .find_map(|chunks| chunks.get(&region)?.get(&index))
+    }
+    /// drops a damaged chunk, so it is generated again. its bytes are kept in the quarantine
+    /// folder in case anyone wants to rescue them, and the whole region file is moved there
+    /// if its header is what is damaged
+    fn quarantine_chunk(&mut self, pos: IVec3, err: &io::Error) {
+        let (region, index) = Self::locate(pos);
+        let path = self.region_path(region);
+        error!("chunk {pos} in {} is damaged, generating it again: {err}", path.display());
+        // removed from the region the next time it is written
+        self.pending.entry(region).or_default().insert(index, None);
+        let Ok(file) = fs::read(&path) else { return };
+        match parse_header(&file) {
+            Ok(entries) => self.shared.keep_damaged(pos, entries[index].raw(&file)),
+            Err(err) => {
+                if let Err(err) = self.shared.quarantine_region(&path, &err) {
+                    error!("couldnt quarantine {}: {err}", path.display());
+                }
+            }
+        }
+    }
+    /// puts the chunks of regions that couldnt be written back into ``pending``,
+    /// unless they were saved again since
+    fn retry(&mut self, chunks: &RegionChunks, failed: &[IVec3]) {
+        for region in failed {
+            let pending = self.pending.entry(*region).or_default();
+            for (index, data) in &chunks[region] {
+                pending.entry(*index).or_insert_with(|| data.clone());
+            }
+        }
+    }
+    /// waits for the background flush to finish
+    fn finish_flush(&mut self) {
+        if let Some(flush) = self.flushing.take() {
+            let failed = future::block_on(flush.task);
+            self.retry(&flush.chunks, &failed);
+        }
+    }
+}
+impl ChunkStore for RegionStore {
+    fn load(&mut self, pos: IVec3) -> Option<BlockData> {
+        if let Some(data) = self.unwritten(pos) {
+            return data.clone();
+        }
+        match self.shared.read_chunk(pos) {
+            Ok(data) => data,
+            Err(err) if is_damage(&err) => {
+                self.quarantine_chunk(pos, &err);
+                None
+            }
+            Err(err) => {
+                let (region, _) = Self::locate(pos);
+                warn!("couldnt load chunk {pos} from {}: {err}", self.region_path(region).display());
+                None
+            }
+        }
+    }
+    fn save(&mut self, pos: IVec3, data: &BlockData) {
+        let (region, index) = Self::locate(pos);
+        self.pending.entry(region).or_default().insert(index, Some(data.clone()));
+    }
+    fn start_load(&mut self, pos: IVec3, generator: Arc<dyn WorldGenerator>) -> Task<LoadedChunk> {
+        let unwritten = self.unwritten(pos).cloned();
+        let shared = Arc::clone(&self.shared);
+        AsyncComputeTaskPool::get().spawn(async move {
+            let read = match unwritten {
+                Some(data) => Ok(data),
+                None => shared.read_chunk(pos),
+            };
+            match read {
+                Ok(Some(data)) => LoadedChunk::Saved(data),
+                Ok(None) => LoadedChunk::Generated(generator.generate(pos)),
+                Err(err) if is_damage(&err) => LoadedChunk::Damaged(generator.generate(pos), err),
+                Err(err) => {
+                    let (region, _) = Self::locate(pos);
+                    warn!("couldnt load chunk {pos} from {}: {err}", shared.region_path(region).display());
+                    LoadedChunk::Generated(generator.generate(pos))
+                }
+            }
+        })
+    }
+    fn damaged(&mut self, pos: IVec3, err: &io::Error) {
+        self.quarantine_chunk(pos, err);
+    }
+    fn flush(&mut self) {
+        self.finish_flush();
+        if self.pending.is_empty() { return; }
+        let chunks = std::mem::take(&mut self.pending);
+        let failed = self.shared.write_chunks(&chunks, &AtomicUsize::new(0));
+        self.retry(&chunks, &failed);
+    }
+    fn start_flush(&mut self) -> usize {
+        if self.flushing.is_some() || self.pending.is_empty() { return 0; }
+        let chunks = Arc::new(std::mem::take(&mut self.pending));
+        let done = Arc::new(AtomicUsize::new(0));
+        let task = IoTaskPool::get().spawn({
+            let (shared, chunks, done) = (Arc::clone(&self.shared), Arc::clone(&chunks), Arc::clone(&done));
+            async move { shared.write_chunks(&chunks, &done) }
+        });
+        let count = chunks.values().map(HashMap::len).sum();
+        self.flushing = Some(Flush { task, chunks, done });
+        count
+    }
+    fn poll_flush(&mut self) -> Option<SaveStatus> {
+        let flush = self.flushing.as_mut()?;
+        let Some(failed) = future::block_on(future::poll_once(&mut flush.task)) else {
+            return Some(SaveStatus::Progress {
+                done: flush.done.load(Ordering::Relaxed),
+                total: flush.chunks.len(),
+            });
+        };
+        let flush = self.flushing.take()?;
+        self.retry(&flush.chunks, &failed);
+        Some(SaveStatus::Finished {
+            chunks: flush.chunks.values().map(HashMap::len).sum(),
+            failed: failed.len(),
+        })
+    }
+    fn unwritten_bytes(&self) -> usize {
+        let flushing = self.flushing.as_ref().map(|flush| &*flush.chunks);
+        [Some(&self.pending), flushing].into_iter().flatten()
+            .flat_map(HashMap::values)
+            .flat_map(HashMap::values)
+            .flatten()
+            .map(ChunkMemory::of_blocks)
+            .sum()
+    }
+    fn set_registry(&mut self, registry: &BlockRegistry) {
+        let mut ids = self.shared.ids();
+        let changed = ids.mapping.changed;
+        ids.mapping = BlockMapping::new(&ids.meta, registry);
+        ids.mapping.changed = changed;
+        ids.registry = registry.clone();
+    }
+}
+
+impl Shared {
+    fn ids(&self) -> MutexGuard<'_, WorldIds> {
+        // a flush that panicked leaves the ids as they were, which is still usable
+        self.ids.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    fn region_path(&self, region: IVec3) -> PathBuf {
+        region_path(&self.dir, region)
+    }
+    fn decode(&self, bytes: &[u8], pos: IVec3) -> io::Result<BlockData> {
+        // generating the base can take a while, and flushes need the ids meanwhile
+        let mapping = self.ids().mapping.clone();
+        decode_chunk(bytes, &mapping, || self.generator.generate(pos))
+    }
+    fn read_chunk(&self, pos: IVec3) -> io::Result<Option<BlockData>> {
+        let (region, index) = RegionStore::locate(pos);
+        let mut file = match File::open(self.region_path(region)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let mut start = [0; START_LEN];
+        file.read_exact(&mut start)?;
+        let version = read_version(&start)?;
+        let entry_len = Entry::len(version);
+        file.seek(SeekFrom::Start((START_LEN + index * entry_len) as u64))?;
+        let mut entry = [0; 12];
+        file.read_exact(&mut entry[..entry_len])?;
+        let entry = Entry::parse(&entry, version);
+        if entry.is_empty() {
+            return Ok(None);
+        }
+        // a damaged header could ask for gigabytes
+        if u64::from(entry.offset) + u64::from(entry.len) > file.metadata()?.len() {
+            return Err(invalid("chunk lies outside of the region file"));
+        }
+        let mut bytes = vec![0; entry.len as usize];
+        file.seek(SeekFrom::Start(u64::from(entry.offset)))?;
+        file.read_exact(&mut bytes)?;
+        entry.verify(&bytes)?;
+        self.decode(&bytes, pos).map(Some)
+    }
+    /// compresses and writes ``chunks`` one region at a time, counting the written regions
+    /// in ``done``. returns the regions that couldnt be written
+    fn write_chunks(&self, chunks: &RegionChunks, done: &AtomicUsize) -> Vec<IVec3> {
+        let mut failed = Vec::new();
+        for (region, chunks) in chunks {
+            let encoded = chunks.iter()
+                .map(|(index, data)| {
+                    let bytes = data.as_ref().and_then(|data| {
+                        let base = self.generator.generate(RegionStore::chunk_pos(*region, *index));
+                        encode_chunk(data, &base, |block| self.ids().to_world(block))
+                    });
+                    (*index, bytes)
+                })
+                .collect();
+            // the region may use ids that only the new metadata has
+            let written = self.write_meta().and_then(|()| self.write_region(*region, &encoded));
+            if let Err(err) = written {
+                error!("couldnt write {}: {err}", self.region_path(*region).display());
+                failed.push(*region);
+            }
+            done.fetch_add(1, Ordering::Relaxed);
+        }
+        failed
+    }
+    /// writes the world metadata if new world ids were added to it
+    fn write_meta(&self) -> io::Result<()> {
+        let meta = {
+            let mut ids = self.ids();
+            if !ids.mapping.changed {
+                return Ok(());
+            }
+            ids.mapping.changed = false;
+            ids.meta.clone()
+        };
+        meta.write(&self.dir).inspect_err(|_| self.ids().mapping.changed = true)
+    }
+    /// rewrites ``region`` with the pending chunks replacing the saved ones
+    fn write_region(&self, region: IVec3, pending: &HashMap<usize, Option<Vec<u8>>>) -> io::Result<()> {
+        let path = self.region_path(region);
+        let mut chunks = self.read_region(region, pending)?;
+        for (index, bytes) in pending {
+            chunks[*index] = bytes.clone();
+        }
+        if chunks.iter().all(Option::is_none) {
+            return match fs::remove_file(&path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+
+        let header_len = Entry::header_len(VERSION);
+        let mut header = Vec::with_capacity(header_len);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        let mut offset = header_len;
+        for chunk in &chunks {
+            let (start, len, checksum) = match chunk {
+                Some(bytes) => (offset, bytes.len(), crc32fast::hash(bytes)),
+                None => (0, 0, 0),
+            };
+            offset += len;
+            let start = u32::try_from(start).map_err(|_| invalid("region file is larger than 4 GiB"))?;
+            header.extend_from_slice(&start.to_le_bytes());
+            header.extend_from_slice(&(len as u32).to_le_bytes());
+            header.extend_from_slice(&checksum.to_le_bytes());
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        let temp = path.with_extension("region.tmp");
+        let mut file = File::create(&temp)?;
+        file.write_all(&header)?;
+        for bytes in chunks.iter().flatten() {
+            file.write_all(bytes)?;
+        }
+        file.sync_all()?;
+        fs::rename(&temp, &path)
+    }
+    /// every intact compressed chunk of ``region``, by index.
+    /// damaged chunks are left out, so the region is written without them, and quarantined
+    /// unless ``pending`` replaces them anyway
+    fn read_region(&self, region: IVec3, pending: &HashMap<usize, Option<Vec<u8>>>) -> io::Result<Vec<Option<Vec<u8>>>> {
+        let path = self.region_path(region);
+        let mut chunks = vec![None; REGION_CHUNKS];
+        let file = match fs::read(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(chunks),
+            Err(err) => return Err(err),
+        };
+        let entries = match parse_header(&file) {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.quarantine_region(&path, &err)?;
+                return Ok(chunks);
+            }
+        };
+        for (index, entry) in entries.iter().enumerate().filter(|(_, entry)| !entry.is_empty()) {
+            match entry.read(&file) {
+                Ok(bytes) => chunks[index] = Some(bytes.to_vec()),
+                Err(_) if pending.contains_key(&index) => {}
+                Err(err) => {
+                    let pos = RegionStore::chunk_pos(region, index);
+                    error!("dropping damaged chunk {pos} from {}: {err}", path.display());
+                    self.keep_damaged(pos, entry.raw(&file));
+                }
+            }
+        }
+        Ok(chunks)
+    }
+    fn quarantine_region(&self, path: &Path, err: &io::Error) -> io::Result<()> {
+        error!("{} is damaged, moving it to the quarantine: {err}", path.display());
+        let quarantine = self.dir.join(QUARANTINE);
+        fs::create_dir_all(&quarantine)?;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        fs::rename(path, quarantine.join(format!("{name}.{}", timestamp())))
+    }
+    fn keep_damaged(&self, pos: IVec3, bytes: &[u8]) {
+        let quarantine = self.dir.join(QUARANTINE);
+        let path = quarantine.join(format!("c.{}.{}.{}.{}.chunk", pos.x, pos.y, pos.z, timestamp()));
+        if let Err(err) = fs::create_dir_all(&quarantine).and_then(|()| fs::write(&path, bytes)) {
+            error!("couldnt keep damaged chunk {pos} in {}: {err}", path.display());
+        }
+    }
+    fn verify_region(&self, path: PathBuf) -> RegionReport {
+        let mut report = RegionReport {
+            path,
+            chunks: 0,
+            damaged: Vec::new(),
+            error: None,
+        };
+        let Some(region) = region_of(&report.path) else {
+            report.error = Some("the file name isnt a region position".to_string());
+            return report;
+        };
+        let (file, entries) = match fs::read(&report.path).and_then(|file| parse_header(&file).map(|entries| (file, entries))) {
+            Ok(header) => header,
+            Err(err) => {
+                report.error = Some(err.to_string());
+                return report;
+            }
+        };
+        for (index, entry) in entries.iter().enumerate().filter(|(_, entry)| !entry.is_empty()) {
+            report.chunks += 1;
+            let pos = RegionStore::chunk_pos(region, index);
+            let decoded = entry.read(&file).and_then(|bytes| self.decode(bytes, pos));
+            if let Err(err) = decoded {
+                report.damaged.push((pos, err.to_string()));
+            }
+        }
+        report
+    }
+}
+
+/// what ``RegionStore::verify`` found in one region file
+pub struct RegionReport {
+    pub path: PathBuf,
+    /// how many chunks the region holds
+    pub chunks: usize,
+    /// the chunks that cant be loaded, and why
+    pub damaged: Vec<(IVec3, String)>,
+    /// set when the file as a whole is unreadable
+    pub error: Option<String>,
+}
+impl RegionReport {
+    pub fn is_intact(&self) -> bool {
+        self.error.is_none() && self.damaged.is_empty()
+    }
+}
+
+/// reads the chunks of a saved world without ever changing its files, unlike ``RegionStore``,
+/// which moves damaged chunks away. any number of threads can read at once: each region file
+/// is opened once, and a read only locks the file it reads from while it reads the bytes.
+///
+/// the chunks are generated with the seed and generator settings the world's ``WorldMeta``
+/// records, since its saved chunks only store how they differ from those
+pub struct RegionReader {
+    dir: PathBuf,
+    mapping: BlockMapping,
+    generator: Arc<dyn WorldGenerator>,
+    /// every region read from so far, ``None`` where there is no file
+    regions: RwLock<HashMap<IVec3, Option<Arc<RegionFile>>>>,
+}
+
+/// an open region file and the entries of its header
+struct RegionFile {
+    entries: Vec<Entry>,
+    len: u64,
+    file: Mutex<File>,
+}
+
+impl RegionReader {
+    /// opens the world saved in ``dir``. ``settings`` is only used for what the world doesnt record
+    pub fn open(dir: impl Into<PathBuf>, settings: &WorldSettings, registry: &BlockRegistry) -> io::Result<Self> {
+        let dir = dir.into();
+        let Some((meta, _)) = WorldMeta::read(&dir)? else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("there is no world in {}", dir.display())));
+        };
+        let Some(generation) = meta.generator.clone() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the world in {} doesnt record what it was generated with, open it as the saved world once", dir.display()),
+            ));
+        };
+        let settings = settings.with_generation(generation, meta.seed);
+        Ok(Self {
+            mapping: BlockMapping::new(&meta, registry),
+            generator: settings.generator.create(&settings, registry)?,
+            dir,
+            regions: RwLock::new(HashMap::new()),
+        })
+    }
+    /// the generator the world was saved with
+    pub fn generator(&self) -> &Arc<dyn WorldGenerator> {
+        &self.generator
+    }
+    /// the saved chunk at ``pos``, or ``None`` if the world doesnt have it
+    pub fn read(&self, pos: IVec3) -> io::Result<Option<BlockData>> {
+        let (region, index) = RegionStore::locate(pos);
+        let Some(region) = self.region(region)? else {
+            return Ok(None);
+        };
+        let entry = region.entries[index];
+        if entry.is_empty() {
+            return Ok(None);
+        }
+        // a damaged header could ask for gigabytes
+        if u64::from(entry.offset) + u64::from(entry.len) > region.len {
+            return Err(invalid("chunk lies outside of the region file"));
+        }
+        let mut bytes = vec![0; entry.len as usize];
+        {
+            let mut file = region.file.lock().unwrap_or_else(PoisonError::into_inner);
+            file.seek(SeekFrom::Start(u64::from(entry.offset)))?;
+            file.read_exact(&mut bytes)?;
+        }
+        entry.verify(&bytes)?;
+        decode_chunk(&bytes, &self.mapping, || self.generator.generate(pos)).map(Some)
+    }
+    /// the open file of ``region``, opening it the first time
+    fn region(&self, region: IVec3) -> io::Result<Option<Arc<RegionFile>>> {
+        if let Some(file) = self.regions.read().unwrap_or_else(PoisonError::into_inner).get(&region) {
+            return Ok(file.clone());
+        }
+        let file = match File::open(region_path(&self.dir, region)) {
+            Ok(mut file) => {
+                let mut header = vec![0; START_LEN];
+                file.read_exact(&mut header)?;
+                header.resize(Entry::header_len(read_version(&header)?), 0);
+                file.read_exact(&mut header[START_LEN..])?;
+                Some(Arc::new(RegionFile {
+                    entries: parse_header(&header)?,
+                    len: file.metadata()?.len(),
+                    file: Mutex::new(file),
+                }))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        // another thread may have opened it meanwhile, which is just as good
+        let mut regions = self.regions.write().unwrap_or_else(PoisonError::into_inner);
+        Ok(regions.entry(region).or_insert(file).clone())
+    }
+}
+
+/// the folder of a world that damaged chunks and regions are moved to
+const QUARANTINE: &str = "quarantine";
+
+/// where a chunk is in its region file
+#[derive(Clone, Copy)]
+struct Entry {
+    offset: u32,
+    len: u32,
+    /// the crc32 of the compressed chunk, missing in version 1 files
+    checksum: Option<u32>,
+}
+impl Entry {
+    fn len(version: u32) -> usize {
+        if version == 1 { 8 } else { 12 }
+    }
+    fn header_len(version: u32) -> usize {
+        START_LEN + REGION_CHUNKS * Self::len(version)
+    }
+    fn parse(bytes: &[u8], version: u32) -> Self {
+        let word = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        Self {
+            offset: word(0),
+            len: word(4),
+            checksum: (version > 1).then(|| word(8)),
+        }
+    }
+    fn is_empty(&self) -> bool {
+        self.offset == 0
+    }
+    fn verify(&self, bytes: &[u8]) -> io::Result<()> {
+        match self.checksum {
+            Some(checksum) if crc32fast::hash(bytes) != checksum => Err(invalid("chunk doesnt match its checksum")),
+            _ => Ok(()),
+        }
+    }
+    /// the chunk's bytes in the whole region ``file``, if they are intact
+    fn read<'a>(&self, file: &'a [u8]) -> io::Result<&'a [u8]> {
+        let bytes = file.get(self.offset as usize..self.offset as usize + self.len as usize)
+            .ok_or_else(|| invalid("chunk lies outside of the region file"))?;
+        self.verify(bytes)?;
+        Ok(bytes)
+    }
+    /// whatever part of the chunk's bytes is in ``file``, without checking them
+    fn raw<'a>(&self, file: &'a [u8]) -> &'a [u8] {
+        let end = (self.offset as usize + self.len as usize).min(file.len());
+        file.get(self.offset as usize..end).unwrap_or_default()
+    }
+}
+
+/// the chunk entries of the whole region ``file``
+fn parse_header(file: &[u8]) -> io::Result<Vec<Entry>> {
+    let version = read_version(file.get(..START_LEN).ok_or_else(|| invalid("region header is truncated"))?)?;
+    let entries = file.get(START_LEN..Entry::header_len(version))
+        .ok_or_else(|| invalid("region header is truncated"))?;
+    Ok(entries.chunks_exact(Entry::len(version)).map(|entry| Entry::parse(entry, version)).collect())
+}
+
+fn read_version(start: &[u8]) -> io::Result<u32> {
+    if &start[..4] != MAGIC {
+        return Err(invalid("not a region file"));
+    }
+    let version = u32::from_le_bytes(start[4..8].try_into().unwrap());
+    if !(1..=VERSION).contains(&version) {
+        return Err(invalid("unsupported region file version"));
+    }
+    Ok(version)
+}
+
+/// the file of ``region`` in the world saved in ``dir``
+fn region_path(dir: &Path, region: IVec3) -> PathBuf {
+    dir.join(format!("r.{}.{}.{}.region", region.x, region.y, region.z))
+}
+
+/// the region a region file holds, from its name
+fn region_of(path: &Path) -> Option<IVec3> {
+    let name = path.file_stem()?.to_str()?;
+    let mut parts = name.strip_prefix("r.")?.split('.').map(str::parse);
+    let region = IVec3::new(parts.next()?.ok()?, parts.next()?.ok()?, parts.next()?.ok()?);
+    parts.next().is_none().then_some(region)
+}
+
+/// errors that mean the saved data is broken, rather than that reading it failed
+fn is_damage(err: &io::Error) -> bool {
+    matches!(err.kind(), io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof)
+}
+
+fn timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+const UNIFORM: u8 = 0;
+const RAW: u8 = 1;
+/// only the voxels that differ from the generated chunk
+const DIFF: u8 = 2;
+/// with more changed voxels than this, storing the whole chunk is smaller than a diff
+const MAX_DIFF: usize = CHUNK_VOLUME / 8;
+
+/// every voxel where ``data`` differs from ``base``, with its block and state
+pub fn diff_chunk(data: &ChunkData, base: &ChunkData) -> Vec<(usize, BlockID, BlockState)> {
+    if data.blocks.uniform().is_some_and(|block| base.blocks.uniform() == Some(block))
+        && data.states.is_empty() && base.states.is_empty()
+    {
+        return Vec::new();
+    }
+    let blocks = data.blocks.to_blocks();
+    let base_blocks = base.blocks.to_blocks();
+    blocks.as_flattened().as_flattened().iter()
+        .zip(base_blocks.as_flattened().as_flattened())
+        .enumerate()
+        .filter_map(|(index, (block, base_block))| {
+            let state = data.states.get(index);
+            (block != base_block || state != base.states.get(index)).then_some((index, *block, state))
+        })
+        .collect()
+}
+
+/// returns ``None`` if ``data`` is the same as the generated ``base``.
+/// a chunk with few changes is stored as the changed voxels, and anything else as a whole:
+/// a uniform chunk is its block id, others are every block id in ``ChunkStorage::index`` order,
+/// followed by the voxels that arent in the default state.
+/// either way it is deflated, and ``world_id`` turns block ids into the ids the world saves them as
+pub fn encode_chunk(data: &ChunkData, base: &ChunkData, mut world_id: impl FnMut(BlockID) -> u16) -> Option<Vec<u8>> {
+    let diff = diff_chunk(data, base);
+    if diff.is_empty() {
+        return None;
+    }
+    let mut raw = Vec::new();
+    if diff.len() <= MAX_DIFF {
+        raw.push(DIFF);
+        raw.extend_from_slice(&(diff.len() as u32).to_le_bytes());
+        for (index, block, state) in diff {
+            raw.extend_from_slice(&(index as u16).to_le_bytes());
+            raw.extend_from_slice(&world_id(block).to_le_bytes());
+            raw.push(state.0);
+        }
+        return Some(deflate(&raw));
+    }
+    match data.blocks.uniform() {
+        Some(block) => {
+            raw.push(UNIFORM);
+            raw.extend_from_slice(&world_id(block).to_le_bytes());
+        }
+        None => {
+            raw.reserve(1 + CHUNK_VOLUME * 2);
+            raw.push(RAW);
+            let mut last = None;
+            for block in data.blocks.to_blocks().as_flattened().as_flattened() {
+                // neighbouring voxels are usually the same block, so skip most lookups
+                let id = match last {
+                    Some((previous, id)) if previous == *block => id,
+                    _ => world_id(*block),
+                };
+                last = Some((*block, id));
+                raw.extend_from_slice(&id.to_le_bytes());
+            }
+        }
+    }
+    let states: Vec<_> = data.states.iter().collect();
+    raw.extend_from_slice(&(states.len() as u32).to_le_bytes());
+    for (index, state) in states {
+        raw.extend_from_slice(&(index as u16).to_le_bytes());
+        raw.push(state.0);
+    }
+    Some(deflate(&raw))
+}
+
+fn deflate(raw: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(raw).expect("writing to a vec cant fail");
+    encoder.finish().expect("writing to a vec cant fail")
+}
+
+/// ``base`` makes the generated chunk, for chunks that were stored as a diff
+pub fn decode_chunk(bytes: &[u8], mapping: &BlockMapping, base: impl FnOnce() -> BlockData) -> io::Result<BlockData> {
+    let mut raw = Vec::new();
+    DeflateDecoder::new(bytes).read_to_end(&mut raw)?;
+    let mut raw = &raw[..];
+
+    let blocks = match take(&mut raw, 1)?[0] {
+        DIFF => {
+            let mut data = base();
+            let edits = Arc::make_mut(&mut data);
+            let count = u32::from_le_bytes(take(&mut raw, 4)?.try_into().unwrap());
+            for _ in 0..count {
+                let entry = take(&mut raw, 5)?;
+                let index = u16::from_le_bytes([entry[0], entry[1]]) as usize;
+                if index >= CHUNK_VOLUME {
+                    return Err(invalid("changed voxel outside of the chunk"));
+                }
+                let block = mapping.to_registry(u16::from_le_bytes([entry[2], entry[3]]));
+                edits.set_with_state(ChunkStorage::position(index), block, BlockState(entry[4]));
+            }
+            return Ok(data);
+        }
+        UNIFORM => ChunkStorage::Uniform(mapping.to_registry(u16::from_le_bytes(take(&mut raw, 2)?.try_into().unwrap()))),
+        RAW => {
+            let mut blocks = Box::new([[[BlockID::AIR; CHUNKSIZE]; CHUNKSIZE]; CHUNKSIZE]);
+            let ids = take(&mut raw, CHUNK_VOLUME * 2)?;
+            for (block, id) in blocks.as_flattened_mut().as_flattened_mut().iter_mut().zip(ids.chunks_exact(2)) {
+                *block = mapping.to_registry(u16::from_le_bytes([id[0], id[1]]));
+            }
+            ChunkStorage::from_blocks(&blocks)
+        }
+        _ => return Err(invalid("unknown chunk encoding")),
+    };
+    let mut data = ChunkData::new(blocks);
+    let count = u32::from_le_bytes(take(&mut raw, 4)?.try_into().unwrap());
+    for _ in 0..count {
+        let entry = take(&mut raw, 3)?;
+        let index = u16::from_le_bytes([entry[0], entry[1]]) as usize;
+        if index >= CHUNK_VOLUME {
+            return Err(invalid("block state outside of the chunk"));
+        }
+        data.states.set(index, BlockState(entry[2]));
+    }
+    Ok(BlockData::new(data))
+}
+
+/// splits the first ``len`` bytes off of ``bytes``
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if bytes.len() < len {
+        return Err(invalid("chunk data is truncated"));
+    }
+    let (start, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fast_voxels::world_gen::{FileGenerator, GeneratorKind};
+
+    /// an empty folder for a test's world, removed again when it is dropped
+    struct TempDir(PathBuf);
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("fast_voxels_{name}_{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            Self(dir)
+        }
+    }
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn settings(seed: u64) -> WorldSettings {
+        WorldSettings {
+            generator: GeneratorKind::Noise,
+            ores: Vec::new(),
+            trees: None,
+            seed,
+            ..default()
+        }
+    }
+
+    fn open(dir: &Path, settings: &WorldSettings) -> io::Result<RegionStore> {
+        let registry = BlockRegistry::default();
+        let generator = settings.generator.create(settings, &registry)?;
+        RegionStore::open(dir, &registry, settings, generator)
+    }
+
+    /// the generated chunk at ``pos`` with a few voxels changed
+    fn edited(settings: &WorldSettings, pos: IVec3) -> BlockData {
+        let generator = settings.generator.create(settings, &BlockRegistry::default()).unwrap();
+        let mut data = (*generator.generate(pos)).clone();
+        for index in [0, 1, 1000, CHUNK_VOLUME - 1] {
+            let local = ChunkStorage::position(index);
+            let block = if data.get(local) == BlockID::STONE { BlockID::AIR } else { BlockID::STONE };
+            data.set(local, block);
+        }
+        BlockData::new(data)
+    }
+
+    /// whether ``a`` and ``b`` hold the same blocks and states, whichever form they are stored in
+    fn same(a: &ChunkData, b: &ChunkData) -> bool {
+        a.blocks.to_blocks() == b.blocks.to_blocks() && (0..CHUNK_VOLUME).all(|index| a.states.get(index) == b.states.get(index))
+    }
+
+    #[test]
+    fn chunks_round_trip_across_regions() {
+        let dir = TempDir::new("round_trip");
+        let settings = settings(3);
+        let generator = settings.generator.create(&settings, &BlockRegistry::default()).unwrap();
+        let edge = REGION_SIZE;
+        // on both sides of region borders, with negative coordinates too
+        let positions = [
+            IVec3::ZERO,
+            IVec3::new(edge - 1, 0, 0),
+            IVec3::new(edge, 0, 0),
+            IVec3::new(-1, -1, -1),
+            IVec3::new(-edge, 0, edge - 1),
+            IVec3::new(-edge - 1, edge - 1, -edge),
+            IVec3::new(2 * edge, -2 * edge - 1, 7),
+            IVec3::new(0, -1, 0),
+        ];
+        let mut saved = Vec::new();
+        for (i, pos) in positions.into_iter().enumerate() {
+            assert_eq!(RegionStore::chunk_pos(RegionStore::locate(pos).0, RegionStore::locate(pos).1), pos);
+            let mut data = (*generator.generate(pos)).clone();
+            // left as generated, a few voxels changed, more changed than a diff is worth, or all one block
+            match i % 4 {
+                0 => {}
+                1 => data = (*edited(&settings, pos)).clone(),
+                2 => {
+                    for index in (0..CHUNK_VOLUME).step_by(3) {
+                        data.set(ChunkStorage::position(index), BlockID::STONE);
+                    }
+                }
+                _ => data = ChunkData::filled(BlockID::GROUND),
+            }
+            data.set_state(ChunkStorage::position(i * 977), BlockState(3));
+            saved.push((pos, data));
+        }
+
+        let mut store = open(&dir.0, &settings).unwrap();
+        for (pos, data) in &saved {
+            store.save(*pos, &BlockData::new(data.clone()));
+        }
+        store.flush();
+        drop(store);
+
+        let mut regions: Vec<_> = positions.iter().map(|pos| RegionStore::locate(*pos).0).collect();
+        regions.sort_by_key(|region| region.to_array());
+        regions.dedup();
+        let files = fs::read_dir(&dir.0).unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension().is_some_and(|ext| ext == "region"))
+            .count();
+        assert_eq!(files, regions.len());
+
+        let mut store = open(&dir.0, &settings).unwrap();
+        for (pos, data) in &saved {
+            let loaded = store.load(*pos).expect("the chunk was saved");
+            assert!(same(&loaded, data), "chunk {pos} changed");
+        }
+        assert!(store.load(IVec3::new(1, 1, 1)).is_none());
+        assert!(store.verify().unwrap().iter().all(RegionReport::is_intact));
+    }
+
+    #[test]
+    fn saved_chunks_load_with_the_same_generation() {
+        let dir = TempDir::new("same_generation");
+        let settings = settings(7);
+        let pos = IVec3::new(1, 0, -2);
+        let data = edited(&settings, pos);
+
+        let mut store = open(&dir.0, &settings).unwrap();
+        store.save(pos, &data);
+        store.flush();
+        drop(store);
+
+        let mut store = open(&dir.0, &settings).unwrap();
+        let loaded = store.load(pos).expect("the chunk was saved");
+        assert_eq!(loaded.blocks.to_blocks(), data.blocks.to_blocks());
+        let meta = WorldMeta::read(&dir.0).unwrap().unwrap().0;
+        assert_eq!(meta.seed, 7);
+        assert_eq!(meta.generator, Some(settings.generation()));
+    }
+
+    #[test]
+    fn other_generation_is_refused() {
+        let dir = TempDir::new("other_generation");
+        let settings = settings(7);
+        let mut store = open(&dir.0, &settings).unwrap();
+        store.save(IVec3::ZERO, &edited(&settings, IVec3::ZERO));
+        store.flush();
+        drop(store);
+
+        let other_seed = self::settings(8);
+        let err = open(&dir.0, &other_seed).err().expect("the seed changed");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let other_generator = WorldSettings { generator: GeneratorKind::Flat, ..settings.clone() };
+        let err = open(&dir.0, &other_generator).err().expect("the generator changed");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(open(&dir.0, &settings).is_ok());
+    }
+
+    /// the names of the files in the quarantine folder of ``dir``
+    fn quarantined(dir: &Path) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(dir.join(QUARANTINE)) else { return Vec::new() };
+        entries.map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect()
+    }
+
+    /// a world in ``dir`` with edited chunks saved at ``positions``, and the path of the region holding the first
+    fn saved_world(dir: &Path, settings: &WorldSettings, positions: &[IVec3]) -> PathBuf {
+        let mut store = open(dir, settings).unwrap();
+        for pos in positions {
+            store.save(*pos, &edited(settings, *pos));
+        }
+        store.flush();
+        region_path(dir, RegionStore::locate(positions[0]).0)
+    }
+
+    #[test]
+    fn chunks_that_fail_their_checksum_are_quarantined() {
+        let dir = TempDir::new("checksum");
+        let settings = settings(5);
+        let (damaged, intact) = (IVec3::new(3, 0, 3), IVec3::new(4, 0, 3));
+        let path = saved_world(&dir.0, &settings, &[damaged, intact]);
+        let mut file = fs::read(&path).unwrap();
+        let entry = parse_header(&file).unwrap()[RegionStore::locate(damaged).1];
+        file[entry.offset as usize] ^= 0x55;
+        fs::write(&path, &file).unwrap();
+
+        let mut store = open(&dir.0, &settings).unwrap();
+        let report = &store.verify().unwrap()[0];
+        assert_eq!(report.chunks, 2);
+        assert_eq!(report.damaged.len(), 1);
+        assert_eq!(report.damaged[0].0, damaged);
+
+        assert!(store.load(damaged).is_none());
+        assert!(store.load(intact).is_some());
+        let kept = quarantined(&dir.0);
+        assert_eq!(kept.len(), 1);
+        assert!(kept[0].starts_with("c.3.0.3."), "{kept:?}");
+
+        // writing the region drops the damaged chunk, so it is generated from then on
+        store.flush();
+        assert!(store.verify().unwrap()[0].is_intact());
+        assert_eq!(store.verify().unwrap()[0].chunks, 1);
+        assert!(store.load(damaged).is_none());
+    }
+
+    #[test]
+    fn truncated_region_files_are_quarantined() {
+        let dir = TempDir::new("truncated");
+        let settings = settings(5);
+        let (first, last) = (IVec3::new(0, 0, 0), IVec3::new(0, 0, 1));
+        let path = saved_world(&dir.0, &settings, &[first, last]);
+        let file = fs::read(&path).unwrap();
+        let entries = parse_header(&file).unwrap();
+        let cut = entries[RegionStore::locate(last).1];
+        let kept = entries[RegionStore::locate(first).1];
+        assert!(kept.offset < cut.offset, "the chunks were written in another order");
+
+        // the end of the last chunk is missing
+        fs::write(&path, &file[..(cut.offset + cut.len / 2) as usize]).unwrap();
+        let mut store = open(&dir.0, &settings).unwrap();
+        assert!(store.load(last).is_none());
+        assert!(store.load(first).is_some());
+        assert_eq!(quarantined(&dir.0).len(), 1);
+
+        // only part of the header is left
+        fs::write(&path, &file[..START_LEN + 100]).unwrap();
+        let mut store = open(&dir.0, &settings).unwrap();
+        assert!(store.verify().unwrap()[0].error.is_some());
+        assert!(store.load(first).is_none());
+        assert!(!path.exists());
+        assert_eq!(quarantined(&dir.0).iter().filter(|name| name.starts_with("r.0.0.0.region.")).count(), 1);
+    }
+
+    #[test]
+    fn region_files_with_bad_headers_are_quarantined() {
+        for (name, start) in [("magic", *b"NOPE\x02\0\0\0"), ("version", *b"VXRG\x63\0\0\0")] {
+            let dir = TempDir::new(&format!("header_{name}"));
+            let settings = settings(5);
+            let pos = IVec3::new(-1, 2, -3);
+            let path = saved_world(&dir.0, &settings, &[pos]);
+            let mut file = fs::read(&path).unwrap();
+            file[..START_LEN].copy_from_slice(&start);
+            fs::write(&path, &file).unwrap();
+
+            let mut store = open(&dir.0, &settings).unwrap();
+            assert!(store.verify().unwrap()[0].error.is_some(), "{name}");
+            assert!(store.load(pos).is_none(), "{name}");
+            assert!(!path.exists(), "{name}");
+            let kept = quarantined(&dir.0);
+            assert!(kept.len() == 1 && kept[0].starts_with("r.-1.0.-1.region."), "{name}: {kept:?}");
+
+            // the next save starts the region over
+            store.save(pos, &edited(&settings, pos));
+            store.flush();
+            assert!(store.verify().unwrap()[0].is_intact(), "{name}");
+        }
+    }
+
+    #[test]
+    fn templates_are_only_read() {
+        let dir = TempDir::new("template");
+        let settings = settings(7);
+        let (intact, damaged) = (IVec3::new(0, 0, 0), IVec3::new(0, 0, 1));
+        let mut store = open(&dir.0, &settings).unwrap();
+        store.save(intact, &edited(&settings, intact));
+        store.save(damaged, &edited(&settings, damaged));
+        store.flush();
+        drop(store);
+
+        let path = region_path(&dir.0, IVec3::ZERO);
+        let mut file = fs::read(&path).unwrap();
+        let entry = parse_header(&file).unwrap()[RegionStore::locate(damaged).1];
+        file[(entry.offset + entry.len - 1) as usize] ^= 0xff;
+        fs::write(&path, &file).unwrap();
+
+        // the template's own seed is used, whatever the settings say
+        let template = FileGenerator::open(dir.0.to_str().unwrap(), &self::settings(8), &BlockRegistry::default()).unwrap();
+        assert_eq!(template.generate(intact).blocks.to_blocks(), edited(&settings, intact).blocks.to_blocks());
+        let generated = settings.generator.create(&settings, &BlockRegistry::default()).unwrap().generate(damaged);
+        assert_eq!(template.generate(damaged).blocks.to_blocks(), generated.blocks.to_blocks());
+        assert_eq!(fs::read(&path).unwrap(), file);
+        assert!(!dir.0.join(QUARANTINE).exists());
+    }
+
+    #[test]
+    fn file_generators_continue_saved_worlds() {
+        let dir = TempDir::new("file_generator");
+        let settings = settings(7);
+        let (saved, unsaved) = (IVec3::new(1, 0, -1), IVec3::new(2, 0, -1));
+        let mut store = open(&dir.0, &settings).unwrap();
+        store.save(saved, &edited(&settings, saved));
+        store.flush();
+        drop(store);
+
+        // the saved world's seed is used, whatever the settings say
+        let file = WorldSettings {
+            generator: GeneratorKind::File { dir: dir.0.to_str().unwrap().to_string() },
+            ..self::settings(8)
+        };
+        let template = file.generator.create(&file, &BlockRegistry::default()).unwrap();
+        assert_eq!(template.generate(saved).blocks.to_blocks(), edited(&settings, saved).blocks.to_blocks());
+        let generated = settings.generator.create(&settings, &BlockRegistry::default()).unwrap().generate(unsaved);
+        assert_eq!(template.generate(unsaved).blocks.to_blocks(), generated.blocks.to_blocks());
+
+        // worlds that dont record what they were generated with cant be continued
+        fs::write(dir.0.join(WorldMeta::FILE_NAME), "(format_version: 2, blocks: {})").unwrap();
+        let Err(err) = file.generator.create(&file, &BlockRegistry::default()) else {
+            panic!("a file generator opened a world without its generator");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,341 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::fast_voxels::{block_registry::BlockRegistry, blocks::BlockID, world_settings::GenerationSettings};
+
+/// the version of the world save format written by this build
+pub const FORMAT_VERSION: u32 = 3;
+
+/// describes a saved world, stored next to its region files.
+/// block ids in the region files are the world's own ids from ``blocks``, so the
+/// registry can renumber its blocks without breaking old saves
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorldMeta {
+    pub format_version: u32,
+    /// block name to the id the world's chunks store it as
+    pub blocks: BTreeMap<String, u16>,
+    /// the seed the world's chunks are generated with
+    #[serde(default)]
+    pub seed: u64,
+    /// ``GenerationSettings::hash`` of ``generator``
+    #[serde(default)]
+    pub generator_hash: u32,
+    /// what the world's chunks are generated with, which the saved ones are stored as differences to.
+    /// worlds from before format 3 dont have it until they are opened
+    #[serde(default)]
+    pub generator: Option<GenerationSettings>,
+}
+impl WorldMeta {
+    pub const FILE_NAME: &str = "world.ron";
+
+    /// the metadata for a world that hasnt been saved yet, using the registry's ids
+    pub fn new(registry: &BlockRegistry, seed: u64, generation: &GenerationSettings) -> Self {
+        let mut meta = Self {
+            format_version: FORMAT_VERSION,
+            blocks: registry.iter().map(|block| (block.name.clone(), block.id.0)).collect(),
+            ..Self::default()
+        };
+        meta.record_generation(seed, generation);
+        meta
+    }
+    /// opens the world in ``dir`` with the chunks generated by ``seed`` and ``generation``, upgrading it
+    /// to ``FORMAT_VERSION``. returns whether it changed, so the caller can write it back.
+    /// a world that was generated with anything else is refused, since its saved chunks only
+    /// store how they differ from what it generated
+    pub fn open(dir: &Path, registry: &BlockRegistry, seed: u64, generation: &GenerationSettings) -> io::Result<(Self, bool)> {
+        let Some((mut meta, mut upgraded)) = Self::read(dir)? else {
+            return Ok((Self::new(registry, seed, generation), true));
+        };
+        if meta.generator.is_some() {
+            meta.check_generation(seed, generation)?;
+        } else {
+            meta.record_generation(seed, generation);
+            upgraded = true;
+        }
+        Ok((meta, upgraded))
+    }
+    /// reads the metadata of the world in ``dir`` and upgrades it to ``FORMAT_VERSION``, without
+    /// writing anything. returns whether it was upgraded, or ``None`` if no world is saved there
+    pub fn read(dir: &Path) -> io::Result<Option<(Self, bool)>> {
+        let path = dir.join(Self::FILE_NAME);
+        let mut meta = match fs::read_to_string(&path) {
+            Ok(text) => ron::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                if !has_regions(dir)? {
+                    return Ok(None);
+                }
+                // the first saves didnt have any metadata
+                Self {
+                    format_version: 1,
+                    ..Self::default()
+                }
+            }
+            Err(err) => return Err(err),
+        };
+        if meta.format_version > FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("world format {} is newer than this build supports", meta.format_version),
+            ));
+        }
+        let upgraded = meta.format_version < FORMAT_VERSION;
+        meta.migrate()?;
+        Ok(Some((meta, upgraded)))
+    }
+    /// fails unless the world's chunks are generated with ``seed`` and ``generation``
+    pub fn check_generation(&self, seed: u64, generation: &GenerationSettings) -> io::Result<()> {
+        let refuse = |message: String| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        if self.seed != seed {
+            return refuse(format!("the world was generated with seed {}, not {seed}", self.seed));
+        }
+        if self.generator_hash != generation.hash() {
+            return refuse(format!(
+                "the world was generated with other generator settings, {:?}",
+                self.generator.as_ref().map(|generator| &generator.generator),
+            ));
+        }
+        Ok(())
+    }
+    fn record_generation(&mut self, seed: u64, generation: &GenerationSettings) {
+        self.seed = seed;
+        self.generator_hash = generation.hash();
+        self.generator = Some(generation.clone());
+    }
+    /// runs every migration from the world's version up to ``FORMAT_VERSION``
+    pub fn migrate(&mut self) -> io::Result<()> {
+        while self.format_version < FORMAT_VERSION {
+            let migration = MIGRATIONS.iter()
+                .find(|migration| migration.from == self.format_version)
+                .ok_or_else(|| io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("no migration from world format {}", self.format_version),
+                ))?;
+            info!("upgrading world format {}: {}", self.format_version, migration.description);
+            (migration.upgrade)(self);
+            self.format_version = migration.from + 1;
+        }
+        Ok(())
+    }
+    /// writes the metadata to ``dir``, through a temporary file so it is never half written
+    pub fn write(&self, dir: &Path) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::create_dir_all(dir)?;
+        let path = dir.join(Self::FILE_NAME);
+        let temp: PathBuf = path.with_extension("ron.tmp");
+        let mut file = File::create(&temp)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp, &path)
+    }
+}
+
+fn has_regions(dir: &Path) -> io::Result<bool> {
+    match fs::read_dir(dir) {
+        Ok(entries) => Ok(entries.flatten().any(|entry| entry.path().extension().is_some_and(|ext| ext == "region"))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// upgrades world metadata saved by an older format version by one version
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    pub upgrade: fn(&mut WorldMeta),
+}
+
+/// every upgrade step, each taking a world from ``from`` to ``from + 1``
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        description: "record the block ids the first saves used",
+        upgrade: |meta| {
+            const BLOCKS: [&str; 16] = [
+                "water", "steam", "ground", "stone", "steel", "copper", "coal", "fire",
+                "oil", "wood", "cloth", "molten_metal", "leaf", "plant", "air", "hydrogen",
+            ];
+            meta.blocks = BLOCKS.iter().zip(0..).map(|(name, id)| (name.to_string(), id)).collect();
+        },
+    },
+    Migration {
+        from: 2,
+        description: "record the seed and generator, taking the ones the world is opened with",
+        // ``WorldMeta::open`` records them, as only it knows them
+        upgrade: |_| {},
+    },
+];
+
+/// translates between the registry's block ids and the ids of one saved world.
+/// blocks the world hasnt seen yet get new world ids, which is why ``WorldMeta`` has to be
+/// written again once ``changed`` is set
+#[derive(Clone)]
+pub struct BlockMapping {
+    /// indexed by world id
+    to_registry: Vec<BlockID>,
+    /// indexed by registry id
+    to_world: Vec<Option<u16>>,
+    placeholder: BlockID,
+    pub changed: bool,
+}
+impl BlockMapping {
+    pub fn new(meta: &WorldMeta, registry: &BlockRegistry) -> Self {
+        let placeholder = registry.placeholder();
+        let mut mapping = Self {
+            to_registry: Vec::new(),
+            to_world: Vec::new(),
+            placeholder,
+            changed: false,
+        };
+        for (name, world_id) in &meta.blocks {
+            match registry.by_name(name) {
+                Some(block) => mapping.link(block, *world_id),
+                None => {
+                    // saved again, these become the placeholder for good
+                    warn!("the world contains block {name:?}, which isnt registered, using a placeholder");
+                    mapping.load_as(*world_id, placeholder);
+                }
+            }
+        }
+        mapping
+    }
+    /// the registry's id for a block saved as ``world_id``
+    #[inline]
+    pub fn to_registry(&self, world_id: u16) -> BlockID {
+        self.to_registry.get(world_id as usize).copied().unwrap_or(self.placeholder)
+    }
+    /// the world's id for ``block``, adding it to ``meta`` if the world doesnt have it yet
+    pub fn to_world(&mut self, block: BlockID, meta: &mut WorldMeta, registry: &BlockRegistry) -> u16 {
+        if let Some(Some(world_id)) = self.to_world.get(block.0 as usize) {
+            return *world_id;
+        }
+        let world_id = meta.blocks.values().max().map_or(0, |max| max + 1);
+        let name = registry.try_get(block)
+            .map_or_else(|| format!("unregistered_{}", block.0), |definition| definition.name.clone());
+        meta.blocks.insert(name, world_id);
+        self.link(block, world_id);
+        self.changed = true;
+        world_id
+    }
+    fn link(&mut self, block: BlockID, world_id: u16) {
+        self.load_as(world_id, block);
+        if self.to_world.len() <= block.0 as usize {
+            self.to_world.resize(block.0 as usize + 1, None);
+        }
+        self.to_world[block.0 as usize] = Some(world_id);
+    }
+    fn load_as(&mut self, world_id: u16, block: BlockID) {
+        if self.to_registry.len() <= world_id as usize {
+            self.to_registry.resize(world_id as usize + 1, self.placeholder);
+        }
+        self.to_registry[world_id as usize] = block;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fast_voxels::world_settings::WorldSettings;
+
+    /// the metadata of a world saved by format 2, which had block ids but no generator
+    const V2_META: &str = r#"(
+        format_version: 2,
+        blocks: {
+            "air": 14,
+            "stone": 3,
+            "wood": 9,
+            "sand": 16,
+        },
+    )"#;
+
+    /// an empty folder for a test's world, removed again when it is dropped
+    struct TempDir(PathBuf);
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("fast_voxels_{name}_{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn worlds_without_metadata_migrate_from_format_1() {
+        // the first saves only had region files
+        let dir = TempDir::new("format_1");
+        fs::write(dir.0.join("0.0.0.region"), []).unwrap();
+        let (meta, upgraded) = WorldMeta::read(&dir.0).unwrap().unwrap();
+        assert!(upgraded);
+        assert_eq!(meta.format_version, FORMAT_VERSION);
+        assert_eq!(meta.blocks.len(), 16);
+        assert_eq!(meta.blocks["water"], 0);
+        assert_eq!(meta.blocks["stone"], 3);
+        assert_eq!(meta.blocks["hydrogen"], 15);
+        assert_eq!(meta.generator, None);
+
+        let settings = WorldSettings { seed: 5, ..default() };
+        let (meta, upgraded) = WorldMeta::open(&dir.0, &BlockRegistry::default(), 5, &settings.generation()).unwrap();
+        assert!(upgraded);
+        assert_eq!(meta.seed, 5);
+        assert_eq!(meta.generator, Some(settings.generation()));
+        assert!(meta.check_generation(5, &settings.generation()).is_ok());
+    }
+
+    #[test]
+    fn format_2_metadata_migrates_keeping_its_blocks() {
+        let dir = TempDir::new("format_2");
+        fs::write(dir.0.join(WorldMeta::FILE_NAME), V2_META).unwrap();
+        let (meta, upgraded) = WorldMeta::read(&dir.0).unwrap().unwrap();
+        assert!(upgraded);
+        assert_eq!(meta.format_version, FORMAT_VERSION);
+        let blocks: Vec<(&str, u16)> = meta.blocks.iter().map(|(name, id)| (name.as_str(), *id)).collect();
+        assert_eq!(blocks, [("air", 14), ("sand", 16), ("stone", 3), ("wood", 9)]);
+
+        let settings = WorldSettings { seed: 9, ..default() };
+        let (meta, upgraded) = WorldMeta::open(&dir.0, &BlockRegistry::default(), 9, &settings.generation()).unwrap();
+        assert!(upgraded);
+        assert_eq!(meta.generator, Some(settings.generation()));
+        meta.write(&dir.0).unwrap();
+        let (written, upgraded) = WorldMeta::read(&dir.0).unwrap().unwrap();
+        assert!(!upgraded);
+        assert_eq!(written, meta);
+    }
+
+    #[test]
+    fn newer_formats_are_refused() {
+        let mut meta = WorldMeta { format_version: 0, ..default() };
+        assert_eq!(meta.migrate().unwrap_err().kind(), io::ErrorKind::Unsupported);
+        let dir = TempDir::new("format_newer");
+        fs::write(dir.0.join(WorldMeta::FILE_NAME), format!("(format_version: {}, blocks: {{}})", FORMAT_VERSION + 1)).unwrap();
+        assert_eq!(WorldMeta::read(&dir.0).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn unregistered_blocks_load_as_the_placeholder() {
+        let registry = BlockRegistry::default();
+        let meta: WorldMeta = ron::from_str(V2_META).unwrap();
+        let mut mapping = BlockMapping::new(&meta, &registry);
+        assert_eq!(mapping.to_registry(3), BlockID::STONE);
+        assert_eq!(mapping.to_registry(9), BlockID::WOOD);
+        // sand isnt registered
+        assert_eq!(mapping.to_registry(16), BlockID::PLACEHOLDER);
+        // neither is an id the world never used
+        assert_eq!(mapping.to_registry(40), BlockID::PLACEHOLDER);
+        assert!(!mapping.changed);
+        let mut meta = meta;
+        assert_eq!(mapping.to_world(BlockID::STONE, &mut meta, &registry), 3);
+        assert!(!mapping.changed);
+    }
+}
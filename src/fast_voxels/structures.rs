@@ -0,0 +1,538 @@
+use std::{cell::OnceCell, fs, io, path::Path, sync::Arc};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    platform::collections::HashMap,
+    prelude::*,
+    tasks::{Task, futures_lite::future},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::fast_voxels::{
+    base_types::{BlockData, CHUNKSIZE, Chunk},
+    block_registry::BlockRegistry,
+    blocks::{BlockID, BlockState},
+    chunk_manager::{ChunkManager, DirtyChunks, GeneratingChunks, Modified},
+    chunk_storage::ChunkData,
+    chunk_store::{LoadedChunk, SaveStore},
+    pipeline::{Earlier, Stage, StagePass},
+    world_gen::{SplitMix, WorldGen, hash},
+};
+
+/// the folder the asset server loads from, for reading templates without it
+pub const ASSET_DIR: &str = "assets";
+
+/// how a ``StructureTemplate`` file is written
+#[derive(Serialize, Deserialize)]
+struct TemplateFile {
+    /// voxels along x, y and z
+    size: [i32; 3],
+    /// the block names ``blocks`` index into. ``None`` leaves the world as it is
+    palette: Vec<Option<String>>,
+    /// one palette index per voxel, x first, then z, then y from the bottom up
+    blocks: Vec<u16>,
+    /// the state of each voxel, in the same order as ``blocks``
+    #[serde(default)]
+    states: Option<Vec<u8>>,
+}
+
+/// a prefabricated piece of voxels, like a machine or a building, loaded from a ``.structure.ron`` file.
+/// it only keeps block names, so ``resolve`` has to look them up before it can be placed
+#[derive(Asset, TypePath, Debug, Clone, PartialEq)]
+pub struct StructureTemplate {
+    pub size: IVec3,
+    pub palette: Vec<Option<String>>,
+    /// palette indices, x first, then z, then y
+    pub blocks: Vec<u16>,
+    /// empty if every voxel has the default state
+    pub states: Vec<BlockState>,
+}
+impl StructureTemplate {
+    pub fn from_ron(text: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let file: TemplateFile = ron::from_str(text).map_err(|err| invalid(err.to_string()))?;
+        let size = IVec3::from_array(file.size);
+        if size.cmple(IVec3::ZERO).any() {
+            return Err(invalid(format!("a structure cant be {size} voxels large")));
+        }
+        let volume = size.x as usize * size.y as usize * size.z as usize;
+        if file.blocks.len() != volume {
+            return Err(invalid(format!("a structure of size {size} needs {volume} blocks, not {}", file.blocks.len())));
+        }
+        if let Some(index) = file.blocks.iter().find(|index| **index as usize >= file.palette.len()) {
+            return Err(invalid(format!("palette index {index} is out of the {} entries of the palette", file.palette.len())));
+        }
+        let states = file.states.unwrap_or_default();
+        if !states.is_empty() && states.len() != volume {
+            return Err(invalid(format!("a structure of size {size} needs {volume} states, not {}", states.len())));
+        }
+        Ok(Self {
+            size,
+            palette: file.palette,
+            blocks: file.blocks,
+            states: states.into_iter().map(BlockState).collect(),
+        })
+    }
+    /// reads the template at ``path``, relative to ``ASSET_DIR``
+    pub fn load(path: &str) -> io::Result<Self> {
+        let text = fs::read_to_string(Path::new(ASSET_DIR).join(path))
+            .map_err(|err| io::Error::new(err.kind(), format!("couldnt read structure {path}: {err}")))?;
+        Self::from_ron(&text).map_err(|err| io::Error::new(err.kind(), format!("invalid structure {path}: {err}")))
+    }
+    /// looks up the blocks of the palette, failing on names that arent registered
+    pub fn resolve(&self, registry: &BlockRegistry) -> io::Result<Structure> {
+        let palette = self.palette.iter()
+            .map(|name| name.as_deref().map(|name| registry.by_name(name).ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("a structure with block {name:?}, which isnt registered"),
+            ))).transpose())
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut voxels = Vec::new();
+        for (i, index) in self.blocks.iter().enumerate() {
+            let Some(block) = palette[*index as usize] else { continue; };
+            let i = i as i32;
+            let pos = IVec3::new(i % self.size.x, i / (self.size.x * self.size.z), i / self.size.x % self.size.z);
+            let state = self.states.get(i as usize).copied().unwrap_or(BlockState::DEFAULT);
+            voxels.push((pos, block, state));
+        }
+        Ok(Structure { size: self.size, voxels })
+    }
+}
+
+/// loads a ``StructureTemplate`` from a ron file
+#[derive(Default, TypePath)]
+pub struct StructureLoader;
+
+impl AssetLoader for StructureLoader {
+    type Asset = StructureTemplate;
+    type Settings = ();
+    type Error = io::Error;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        StructureTemplate::from_ron(&String::from_utf8_lossy(&bytes))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["structure.ron"]
+    }
+}
+
+/// how a structure is turned when it is placed. block states are placed as they are,
+/// so blocks that face somewhere dont turn with it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Orientation {
+    /// quarter turns around the y axis, from +x towards +z
+    pub turns: u8,
+    /// flips x before turning
+    pub mirror: bool,
+}
+impl Orientation {
+    /// the size of something ``size`` large once it is turned
+    pub fn size(self, size: IVec3) -> IVec3 {
+        if self.turns % 2 == 0 { size } else { IVec3::new(size.z, size.y, size.x) }
+    }
+    /// where the voxel at ``pos`` of something ``size`` large ends up once it is turned.
+    /// it stays within the same corner, so the turned size starts at zero too
+    pub fn apply(self, pos: IVec3, size: IVec3) -> IVec3 {
+        let x = if self.mirror { size.x - 1 - pos.x } else { pos.x };
+        match self.turns % 4 {
+            0 => IVec3::new(x, pos.y, pos.z),
+            1 => IVec3::new(size.z - 1 - pos.z, pos.y, x),
+            2 => IVec3::new(size.x - 1 - x, pos.y, size.z - 1 - pos.z),
+            _ => IVec3::new(pos.z, pos.y, size.x - 1 - x),
+        }
+    }
+}
+
+/// a ``StructureTemplate`` with its blocks looked up, ready to be placed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Structure {
+    pub size: IVec3,
+    /// the voxels that arent left as they are, by position in the template
+    voxels: Vec<(IVec3, BlockID, BlockState)>,
+}
+impl Structure {
+    /// the blocks of the structure placed with its lowest corner at the world position ``origin``
+    pub fn placed(&self, origin: IVec3, orientation: Orientation) -> impl Iterator<Item = (IVec3, BlockID, BlockState)> + '_ {
+        self.voxels.iter().map(move |(pos, block, state)| (origin + orientation.apply(*pos, self.size), *block, *state))
+    }
+    /// writes the part of the placed structure that lies in the chunk at ``chunk_pos`` into ``data``,
+    /// returning whether it changed anything
+    pub fn place_into(&self, origin: IVec3, orientation: Orientation, chunk_pos: IVec3, data: &mut ChunkData) -> bool {
+        let chunk_min = chunk_pos * CHUNKSIZE as i32;
+        let max = origin + orientation.size(self.size);
+        if origin.cmpge(chunk_min + CHUNKSIZE as i32).any() || max.cmple(chunk_min).any() {
+            return false;
+        }
+        let mut changed = false;
+        for (pos, block, state) in self.placed(origin, orientation) {
+            let local = pos - chunk_min;
+            if local.cmplt(IVec3::ZERO).any() || local.cmpge(IVec3::splat(CHUNKSIZE as i32)).any() { continue; }
+            if data.get(local) == block && data.state(local) == state { continue; }
+            data.set_with_state(local, block, state);
+            changed = true;
+        }
+        changed
+    }
+}
+
+/// where ``StructurePlacer`` puts a structure
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StructureRule {
+    /// the template file, relative to the assets folder
+    pub template: String,
+    /// the world is divided into squares this many voxels wide, each of which has at most one
+    pub spacing: i32,
+    /// the chance for one in each square
+    pub chance: f32,
+    /// how many voxels of it are sunk into the ground
+    pub depth: i32,
+    /// the lowest and highest ground it is placed on
+    pub min_y: i32,
+    pub max_y: i32,
+    /// whether it is turned and mirrored at random, otherwise it is placed as it is in the file
+    pub rotate: bool,
+}
+impl Default for StructureRule {
+    fn default() -> Self {
+        Self {
+            template: String::new(),
+            spacing: 128,
+            chance: 0.5,
+            depth: 1,
+            min_y: 0,
+            max_y: 256,
+            rotate: true,
+        }
+    }
+}
+
+struct Placement {
+    rule: StructureRule,
+    structure: Structure,
+    seed: u64,
+}
+
+/// places structures on ground that has air above it, in the ``Stage::Decorate`` stage, by ``StructureRule``s.
+/// each chunk looks for the structures of the chunks around it that reach into it, so structures
+/// come out whole across chunk borders
+pub struct StructurePlacer {
+    placements: Vec<Placement>,
+}
+impl StructurePlacer {
+    /// reads the template of every rule
+    pub fn new(seed: u64, rules: &[StructureRule], registry: &BlockRegistry) -> io::Result<Self> {
+        let placements = rules.iter().zip(0..)
+            .map(|(rule, index)| {
+                if rule.spacing <= 0 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("structure {} needs a spacing above 0", rule.template)));
+                }
+                Ok(Placement {
+                    rule: rule.clone(),
+                    structure: StructureTemplate::load(&rule.template)?.resolve(registry)?,
+                    seed: hash(seed, 8, index),
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self { placements })
+    }
+    /// where the structure of ``placement`` in the square at ``cell`` stands, and how it is turned, if it has one
+    fn roll(placement: &Placement, cell: IVec2) -> Option<(IVec2, Orientation)> {
+        let mut random = SplitMix(hash(placement.seed, cell.x, cell.y));
+        if random.next_f32() >= placement.rule.chance {
+            return None;
+        }
+        let spacing = placement.rule.spacing as u64;
+        let column = cell * placement.rule.spacing + IVec2::new((random.next_u64() % spacing) as i32, (random.next_u64() % spacing) as i32);
+        let orientation = Orientation { turns: (random.next_u64() % 4) as u8, mirror: random.next_u64() % 2 == 1 };
+        Some((column, if placement.rule.rotate { orientation } else { Orientation::default() }))
+    }
+}
+impl StagePass for StructurePlacer {
+    fn stage(&self) -> Stage {
+        Stage::Decorate
+    }
+    fn reach(&self) -> IVec3 {
+        let size = self.placements.iter()
+            .map(|placement| placement.structure.size.max(IVec3::splat(placement.rule.depth)))
+            .fold(IVec3::ZERO, IVec3::max);
+        let across = size.x.max(size.z);
+        // one more up, for what lies on the ground at the top of the highest chunks structures stand in
+        IVec3::new(across, size.y, across).map(|extent| (extent + CHUNKSIZE as i32 - 1) / CHUNKSIZE as i32) + IVec3::Y
+    }
+    fn run(&self, chunk_pos: IVec3, data: &mut BlockData, earlier: &Earlier) {
+        if self.placements.is_empty() { return; }
+        let data = Arc::make_mut(data);
+        // the chunks structures can stand in
+        let reach = self.reach() - IVec3::Y;
+        // always in the same order, so where structures overlap the same one wins
+        for dx in -reach.x..=reach.x {
+            for dy in -reach.y..=reach.y {
+                for dz in -reach.z..=reach.z {
+                    let source_pos = chunk_pos + IVec3::new(dx, dy, dz);
+                    let origin = source_pos * CHUNKSIZE as i32;
+                    let mut source = None;
+                    let above = OnceCell::new();
+                    for placement in &self.placements {
+                        let rule = &placement.rule;
+                        if origin.y + (CHUNKSIZE as i32) <= rule.min_y || origin.y > rule.max_y { continue; }
+                        let min = origin.xz().div_euclid(IVec2::splat(rule.spacing));
+                        let max = (origin.xz() + CHUNKSIZE as i32 - 1).div_euclid(IVec2::splat(rule.spacing));
+                        for x in min.x..=max.x {
+                            for z in min.y..=max.y {
+                                let Some((column, orientation)) = Self::roll(placement, IVec2::new(x, z)) else { continue; };
+                                let local = column - origin.xz();
+                                if local.cmplt(IVec2::ZERO).any() || local.cmpge(IVec2::splat(CHUNKSIZE as i32)).any() { continue; }
+                                let source = source.get_or_insert_with(|| earlier.get(source_pos));
+                                if source.uniform().is_some_and(|block| block != BlockID::GROUND) { continue; }
+                                // the highest ground in the column with air on top, which is in the chunk above at the top
+                                let Some(y) = (0..CHUNKSIZE as i32).rev().find(|y| {
+                                    source.get(IVec3::new(local.x, *y, local.y)) == BlockID::GROUND && match y + 1 {
+                                        top if top < CHUNKSIZE as i32 => source.get(IVec3::new(local.x, top, local.y)),
+                                        _ => above.get_or_init(|| earlier.get(source_pos + IVec3::Y)).get(IVec3::new(local.x, 0, local.y)),
+                                    } == BlockID::AIR
+                                }) else { continue; };
+                                let ground = origin.y + y;
+                                if !(rule.min_y..=rule.max_y).contains(&ground) { continue; }
+                                // centred on the column
+                                let size = orientation.size(placement.structure.size);
+                                let corner = IVec3::new(column.x - size.x / 2, ground + 1 - rule.depth, column.y - size.z / 2);
+                                placement.structure.place_into(corner, orientation, chunk_pos, data);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// places a ``StructureTemplate`` at runtime once it is loaded, with its lowest corner at ``origin``.
+/// it replaces whatever is there. the parts in chunks that arent loaded are saved into them
+#[derive(Message, Debug, Clone)]
+pub struct PlaceStructure {
+    pub template: Handle<StructureTemplate>,
+    pub origin: IVec3,
+    pub orientation: Orientation,
+}
+
+/// a structure, the world position of its lowest corner and how it is turned
+type Placed = (Arc<Structure>, IVec3, Orientation);
+
+/// places the ``PlaceStructure``s whose templates are loaded, keeping the others until they are.
+/// chunks that arent loaded are loaded or generated on the ``AsyncComputeTaskPool``, and once that
+/// finishes their part is placed and saved. the chunk systems then load the saved chunk, so every
+/// part of a structure ends up in the world
+pub fn place_structures(
+    mut commands: Commands,
+    mut requests: MessageReader<PlaceStructure>,
+    mut waiting: Local<Vec<PlaceStructure>>,
+    mut unloaded: Local<HashMap<IVec3, (Task<LoadedChunk>, Vec<Placed>)>>,
+    templates: Res<Assets<StructureTemplate>>,
+    asset_server: Res<AssetServer>,
+    registry: Res<BlockRegistry>,
+    world_gen: Res<WorldGen>,
+    mut store: ResMut<SaveStore>,
+    mut chunk_manager: ResMut<ChunkManager>,
+    mut generating: ResMut<GeneratingChunks>,
+    mut dirty: ResMut<DirtyChunks>,
+    mut chunks: Query<&mut Chunk>,
+) {
+    waiting.extend(requests.read().cloned());
+    if waiting.is_empty() && unloaded.is_empty() { return; }
+
+    let mut parts = Vec::new();
+    let mut still_waiting = Vec::new();
+    for request in waiting.drain(..) {
+        let Some(template) = templates.get(&request.template) else {
+            if asset_server.load_state(&request.template).is_failed() {
+                error!("couldnt place structure at {}, its template failed to load", request.origin);
+            } else {
+                still_waiting.push(request);
+            }
+            continue;
+        };
+        let structure = match template.resolve(&registry) {
+            Ok(structure) => Arc::new(structure),
+            Err(err) => {
+                error!("couldnt place structure at {}: {err}", request.origin);
+                continue;
+            }
+        };
+        let max = request.origin + request.orientation.size(structure.size) - 1;
+        let (min, max) = (request.origin.div_euclid(IVec3::splat(CHUNKSIZE as i32)), max.div_euclid(IVec3::splat(CHUNKSIZE as i32)));
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    parts.push((IVec3::new(x, y, z), (Arc::clone(&structure), request.origin, request.orientation)));
+                }
+            }
+        }
+    }
+    *waiting = still_waiting;
+
+    let mut done = Vec::new();
+    for (chunk_pos, (task, _)) in unloaded.iter_mut() {
+        if let Some(loaded) = future::block_on(future::poll_once(task)) {
+            done.push((*chunk_pos, loaded));
+        }
+    }
+    for (chunk_pos, loaded) in done {
+        let Some((_, placed)) = unloaded.remove(&chunk_pos) else { continue; };
+        if chunk_manager.map.contains_key(&chunk_pos) {
+            // the chunk systems loaded it meanwhile
+            parts.extend(placed.into_iter().map(|placed| (chunk_pos, placed)));
+            continue;
+        }
+        if let LoadedChunk::Damaged(_, err) = &loaded {
+            store.0.damaged(chunk_pos, err);
+        }
+        let mut data = loaded.data();
+        let mut changed = false;
+        for (structure, origin, orientation) in placed {
+            changed |= structure.place_into(origin, orientation, chunk_pos, Arc::make_mut(&mut data));
+        }
+        if changed {
+            store.0.save(chunk_pos, &data);
+            // started over, so it loads with the structure
+            generating.0.remove(&chunk_pos);
+        }
+    }
+
+    for (chunk_pos, (structure, origin, orientation)) in parts {
+        let Some(data) = chunk_manager.map.get_mut(&chunk_pos) else {
+            let (_, placed) = unloaded.entry(chunk_pos)
+                .or_insert_with(|| (store.0.start_load(chunk_pos, Arc::clone(&world_gen.0)), Vec::new()));
+            placed.push((structure, origin, orientation));
+            continue;
+        };
+        if !structure.place_into(origin, orientation, chunk_pos, Arc::make_mut(data)) { continue; }
+        let data = Arc::clone(data);
+        if let Some(&entity) = chunk_manager.entities.get(&chunk_pos)
+            && let Ok(mut chunk) = chunks.get_mut(entity)
+        {
+            chunk.data = data;
+            commands.entity(entity).insert(Modified);
+        }
+        dirty.mark(chunk_pos);
+        dirty.mark_neighbors(chunk_pos);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+    use crate::fast_voxels::{
+        pipeline::GenerationPipeline,
+        region_store::diff_chunk,
+        world_gen::{FlatGenerator, WorldGenerator},
+    };
+
+    /// every turn, mirrored and not
+    fn orientations() -> impl Iterator<Item = Orientation> {
+        (0..4).flat_map(|turns| [false, true].map(|mirror| Orientation { turns, mirror }))
+    }
+
+    /// a template ``size`` large with no symmetry, of stone and wood with some voxels left as they are
+    fn template_text(size: IVec3) -> String {
+        let volume = size.x * size.y * size.z;
+        let blocks: Vec<String> = (0..volume).map(|i| (i * 7 % 5 % 3).to_string()).collect();
+        format!(
+            "(size: ({}, {}, {}), palette: [None, Some(\"stone\"), Some(\"wood\")], blocks: [{}])",
+            size.x, size.y, size.z, blocks.join(", "),
+        )
+    }
+
+    #[test]
+    fn orientations_turn_templates_into_their_turned_size() {
+        let size = IVec3::new(2, 3, 5);
+        let template: Vec<IVec3> = (0..size.x)
+            .flat_map(|x| (0..size.y).flat_map(move |y| (0..size.z).map(move |z| IVec3::new(x, y, z))))
+            .collect();
+        let mut turned_templates = HashSet::new();
+        for orientation in orientations() {
+            let turned_size = orientation.size(size);
+            let turned: Vec<IVec3> = template.iter().map(|pos| orientation.apply(*pos, size)).collect();
+            for pos in &turned {
+                assert!(pos.cmpge(IVec3::ZERO).all() && pos.cmplt(turned_size).all(), "{pos} is outside of {turned_size} turned by {orientation:?}");
+            }
+            assert_eq!(turned.iter().collect::<HashSet<_>>().len(), template.len(), "{orientation:?} puts two voxels in one place");
+            turned_templates.insert(turned);
+        }
+        assert_eq!(turned_templates.len(), 8);
+    }
+
+    #[test]
+    fn invalid_templates_are_refused() {
+        assert!(StructureTemplate::from_ron(&template_text(IVec3::new(3, 2, 4))).is_ok());
+        let invalid = [
+            "(size: (0, 1, 1), palette: [None], blocks: [])",
+            "(size: (-1, 1, 1), palette: [None], blocks: [0])",
+            "(size: (2, 1, 1), palette: [None], blocks: [0])",
+            "(size: (2, 1, 1), palette: [None, Some(\"stone\")], blocks: [1, 2])",
+            "(size: (2, 1, 1), palette: [None], blocks: [0, 0], states: Some([0]))",
+        ];
+        for text in invalid {
+            let err = StructureTemplate::from_ron(text).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{text}");
+        }
+    }
+
+    #[test]
+    fn structures_across_chunk_corners_come_out_whole_in_any_order() {
+        let structure = StructureTemplate::from_ron(&template_text(IVec3::new(5, 3, 7)))
+            .unwrap()
+            .resolve(&BlockRegistry::default())
+            .unwrap();
+        let rule = StructureRule { spacing: 64, ..default() };
+        let placer = |seed| StructurePlacer {
+            placements: vec![Placement { rule: rule.clone(), structure: structure.clone(), seed }],
+        };
+        // the surface is the top of the chunks at y 0, so the structures reach into the ones above
+        let pipeline = |seed| GenerationPipeline::new(Arc::new(FlatGenerator { height: CHUNKSIZE as i32, block: BlockID::GROUND }))
+            .with_pass(Arc::new(placer(seed)));
+        let chunks_of = |corner, orientation| structure.placed(corner, orientation)
+            .map(|(pos, _, _)| pos.div_euclid(IVec3::splat(CHUNKSIZE as i32)))
+            .collect::<HashSet<_>>();
+        // the first seed with a structure whose blocks lie in the chunks on every side of the corner
+        // at the origin, and none in the squares around it
+        let (seed, corner, orientation) = (0..)
+            .find_map(|seed| {
+                let placer = placer(seed);
+                let placement = &placer.placements[0];
+                let others = [IVec2::NEG_ONE, IVec2::NEG_X, IVec2::NEG_Y];
+                if others.iter().any(|cell| StructurePlacer::roll(placement, *cell).is_some()) { return None; }
+                let (column, orientation) = StructurePlacer::roll(placement, IVec2::ZERO)?;
+                let size = orientation.size(structure.size);
+                let corner = IVec3::new(column.x - size.x / 2, CHUNKSIZE as i32 - rule.depth, column.y - size.z / 2);
+                (chunks_of(corner, orientation).len() == 8).then_some((seed, corner, orientation))
+            })
+            .unwrap();
+        let chunks: Vec<IVec3> = chunks_of(corner, orientation).into_iter().collect();
+        let reference = pipeline(seed);
+        for first in &chunks {
+            let pipeline = pipeline(seed);
+            let mut generated = HashMap::new();
+            for pos in std::iter::once(first).chain(&chunks) {
+                generated.entry(*pos).or_insert_with(|| pipeline.generate(*pos));
+            }
+            for (pos, block, _) in structure.placed(corner, orientation) {
+                let chunk_pos = pos.div_euclid(IVec3::splat(CHUNKSIZE as i32));
+                assert_eq!(generated[&chunk_pos].get(pos - chunk_pos * CHUNKSIZE as i32), block, "{pos} with chunk {first} generated first");
+            }
+            for pos in &chunks {
+                assert!(diff_chunk(&generated[pos], &reference.generate(*pos)).is_empty(), "chunk {pos} with chunk {first} generated first");
+            }
+        }
+    }
+}
@@ -0,0 +1,808 @@
+use std::{
+    f32::consts::TAU,
+    io,
+    sync::Arc,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::fast_voxels::{
+    base_types::{BlockData, CHUNKSIZE},
+    biomes::{BiomeMap, BiomeSettings},
+    block_registry::BlockRegistry,
+    blocks::BlockID,
+    chunk_storage::ChunkData,
+    decoration::TreeDecorator,
+    heightmap::{HeightmapGenerator, HeightmapSettings},
+    ores::OreGenerator,
+    pipeline::{Earlier, GenerationPipeline, LightPass, Stage, StagePass},
+    region_store::RegionReader,
+    structures::StructurePlacer,
+    world_settings::WorldSettings,
+};
+
+/// makes the terrain a chunk has before any edits. saves only store how chunks differ from it,
+/// so it has to give the same result for the same position every time, and a world has to
+/// keep using the generator it was saved with
+pub trait WorldGenerator: Send + Sync {
+    fn generate(&self, chunk_pos: IVec3) -> BlockData;
+    /// the bytes of the chunks the generator keeps around, which ``MemoryBudget`` counts
+    fn cache_bytes(&self) -> usize {
+        0
+    }
+    /// forgets the chunks used longest ago, until at most ``max_bytes`` are cached
+    fn trim_cache(&self, _max_bytes: usize) {}
+}
+
+/// the generator new chunks are made with. built from ``WorldSettings::generator``
+/// unless the app inserts its own
+#[derive(Resource, Clone)]
+pub struct WorldGen(pub Arc<dyn WorldGenerator>);
+
+/// the generators ``WorldSettings`` can choose from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum GeneratorKind {
+    /// ``TerrainGenerator``, seeded with ``WorldSettings::seed``
+    Terrain(TerrainSettings),
+    /// ``CaveGenerator``, seeded with ``WorldSettings::seed``
+    Caves {
+        #[serde(default)]
+        terrain: TerrainSettings,
+        #[serde(default)]
+        caves: CaveSettings,
+    },
+    /// ``HeightmapGenerator``, with terrain painted as images
+    Heightmap(HeightmapSettings),
+    /// ``FlatGenerator``
+    Flat,
+    /// ``CheckerboardGenerator``
+    Checkerboard,
+    /// ``NoiseGenerator``, seeded with ``WorldSettings::seed``
+    Noise,
+    /// ``FileGenerator`` with the world saved in ``dir``, continued with the seed and generator it was saved with
+    File { dir: String },
+}
+impl Default for GeneratorKind {
+    fn default() -> Self {
+        Self::Terrain(TerrainSettings::default())
+    }
+}
+impl GeneratorKind {
+    /// the ``GenerationPipeline`` that makes the terrain, carves it, places ``WorldSettings::ores``,
+    /// ``WorldSettings::trees`` and ``WorldSettings::structures`` into it, and lights it. a saved world is used as it is
+    pub fn create(&self, settings: &WorldSettings, registry: &BlockRegistry) -> io::Result<Arc<dyn WorldGenerator>> {
+        let mut biomes = None;
+        let mut pipeline = match self {
+            Self::Terrain(terrain) => {
+                let terrain = TerrainGenerator::new(settings.seed, terrain.clone(), registry)?;
+                biomes = terrain.biomes.clone();
+                GenerationPipeline::new(Arc::new(terrain))
+            }
+            Self::Caves { terrain, caves } => {
+                let caves = Arc::new(CaveGenerator {
+                    terrain: TerrainGenerator::new(settings.seed, terrain.clone(), registry)?,
+                    settings: caves.clone(),
+                });
+                biomes = caves.terrain.biomes.clone();
+                GenerationPipeline::new(caves.clone()).with_pass(caves)
+            }
+            Self::Heightmap(heightmap) => GenerationPipeline::new(Arc::new(HeightmapGenerator::open(heightmap.clone(), registry)?)),
+            Self::Flat => GenerationPipeline::new(Arc::new(FlatGenerator::default())),
+            Self::Checkerboard => GenerationPipeline::new(Arc::new(CheckerboardGenerator::default())),
+            Self::Noise => GenerationPipeline::new(Arc::new(NoiseGenerator::new(settings.seed))),
+            Self::File { dir } => return Ok(Arc::new(FileGenerator::open(dir, settings, registry)?)),
+        };
+        if !settings.ores.is_empty() {
+            pipeline = pipeline.with_pass(Arc::new(OreGenerator::new(settings.seed, &settings.ores, registry)?));
+        }
+        if let Some(trees) = &settings.trees {
+            pipeline = pipeline.with_pass(Arc::new(TreeDecorator {
+                seed: hash(settings.seed, 5, 0),
+                settings: trees.clone(),
+                biomes,
+            }));
+        }
+        if !settings.structures.is_empty() {
+            // after the trees, so structures clear the ones where they stand
+            pipeline = pipeline.with_pass(Arc::new(StructurePlacer::new(settings.seed, &settings.structures, registry)?));
+        }
+        Ok(Arc::new(pipeline.with_pass(Arc::new(LightPass))))
+    }
+    /// the settings of the terrain, for the generators that have it
+    pub fn terrain(&self) -> Option<&TerrainSettings> {
+        match self {
+            Self::Terrain(terrain) | Self::Caves { terrain, .. } => Some(terrain),
+            _ => None,
+        }
+    }
+}
+
+/// ``block`` below ``height``, air above it
+pub struct FlatGenerator {
+    /// the world y of the lowest air voxel
+    pub height: i32,
+    pub block: BlockID,
+}
+impl Default for FlatGenerator {
+    fn default() -> Self {
+        Self {
+            height: CHUNKSIZE as i32,
+            block: BlockID::STONE,
+        }
+    }
+}
+impl WorldGenerator for FlatGenerator {
+    fn generate(&self, chunk_pos: IVec3) -> BlockData {
+        let bottom = chunk_pos.y * CHUNKSIZE as i32;
+        let layers = (self.height - bottom).clamp(0, CHUNKSIZE as i32);
+        let mut data = ChunkData::filled(if layers == 0 { BlockID::AIR } else { self.block });
+        if (1..CHUNKSIZE as i32).contains(&layers) {
+            fill_columns(&mut data, |_, _| layers, |_| BlockID::AIR, |_| self.block);
+        }
+        BlockData::new(data)
+    }
+}
+
+/// every other voxel is ``block``, in every chunk. the worst case for the meshers,
+/// since no two faces can be merged and none are hidden
+pub struct CheckerboardGenerator {
+    pub block: BlockID,
+}
+impl Default for CheckerboardGenerator {
+    fn default() -> Self {
+        Self {
+            block: BlockID::STONE,
+        }
+    }
+}
+impl WorldGenerator for CheckerboardGenerator {
+    fn generate(&self, _chunk_pos: IVec3) -> BlockData {
+        // chunks have an even size, so every chunk is the same
+        let mut data = ChunkData::filled(BlockID::AIR);
+        for x in 0..CHUNKSIZE as i32 {
+            for y in 0..CHUNKSIZE as i32 {
+                for z in ((x + y) % 2..CHUNKSIZE as i32).step_by(2) {
+                    data.set(IVec3::new(x, y, z), self.block);
+                }
+            }
+        }
+        BlockData::new(data)
+    }
+}
+
+/// rolling hills of ``block``, from smoothly interpolated random heights on a grid
+pub struct NoiseGenerator {
+    pub seed: u64,
+    pub block: BlockID,
+    /// the world y the hills are centred on
+    pub height: i32,
+    /// how far the hills reach above and below ``height``
+    pub amplitude: f32,
+    /// the distance between the random heights, in voxels
+    pub scale: f32,
+}
+impl NoiseGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            block: BlockID::STONE,
+            height: CHUNKSIZE as i32,
+            amplitude: 24.0,
+            scale: 48.0,
+        }
+    }
+    /// the world y of the lowest air voxel of the column at ``x``, ``z``
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        let noise = value_noise(self.seed, x as f32 / self.scale, z as f32 / self.scale);
+        self.height + (noise * self.amplitude).round() as i32
+    }
+}
+impl WorldGenerator for NoiseGenerator {
+    fn generate(&self, chunk_pos: IVec3) -> BlockData {
+        let origin = chunk_pos * CHUNKSIZE as i32;
+        let bottom = self.height - self.amplitude.ceil() as i32;
+        let top = self.height + self.amplitude.ceil() as i32;
+        if origin.y >= top {
+            return BlockData::new(ChunkData::filled(BlockID::AIR));
+        }
+        if origin.y + (CHUNKSIZE as i32) <= bottom {
+            return BlockData::new(ChunkData::filled(self.block));
+        }
+        let mut data = ChunkData::filled(BlockID::AIR);
+        fill_columns(
+            &mut data,
+            |x, z| self.height_at(origin.x + x, origin.z + z) - origin.y,
+            |_| BlockID::AIR,
+            |_| self.block,
+        );
+        BlockData::new(data)
+    }
+}
+
+/// how ``TerrainGenerator`` shapes the world
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerrainSettings {
+    /// the world y the surface is centred on
+    pub height: i32,
+    /// how far the surface reaches above and below ``height``
+    pub amplitude: f32,
+    /// the size of the largest hills, in voxels
+    pub scale: f32,
+    /// how many layers of ever finer noise make up the surface
+    pub octaves: u32,
+    /// how much weaker each octave is than the one before
+    pub persistence: f32,
+    /// how much finer each octave is than the one before
+    pub lacunarity: f32,
+    /// how many voxels of ground lie on top of the stone
+    pub ground_depth: i32,
+    /// the world y of the lowest voxel that isnt filled with water
+    pub sea_level: i32,
+    /// the biomes that reshape and cover the terrain. without them it is the same everywhere
+    pub biomes: Option<BiomeSettings>,
+}
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self {
+            height: 32,
+            amplitude: 40.0,
+            scale: 256.0,
+            octaves: 5,
+            persistence: 0.5,
+            lacunarity: 2.0,
+            ground_depth: 4,
+            sea_level: 28,
+            biomes: Some(BiomeSettings::default()),
+        }
+    }
+}
+
+/// the surface of one column of terrain, and what covers the stone below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    /// the world y of the lowest voxel above the surface
+    pub surface: i32,
+    pub cover: BlockID,
+    /// how many voxels of ``cover`` lie on the stone
+    pub depth: i32,
+}
+
+/// hills and valleys from several octaves of noise, with ground on top of stone,
+/// and water up to the sea level wherever the surface is below it.
+/// with biomes, each biome has hills of its own and its own cover instead of ground
+pub struct TerrainGenerator {
+    pub seed: u64,
+    pub settings: TerrainSettings,
+    pub biomes: Option<Arc<BiomeMap>>,
+}
+impl TerrainGenerator {
+    /// the generator for ``settings``, looking up the blocks its biomes are covered with in ``registry``
+    pub fn new(seed: u64, settings: TerrainSettings, registry: &BlockRegistry) -> io::Result<Self> {
+        let biomes = match &settings.biomes {
+            Some(biomes) => Some(Arc::new(BiomeMap::new(seed, biomes, registry)?)),
+            None => None,
+        };
+        Ok(Self { seed, settings, biomes })
+    }
+    /// the world y of the lowest voxel above the surface of the column at ``x``, ``z``
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        self.column_at(x, z).surface
+    }
+    /// the column at ``x``, ``z``. its shape is blended from every biome close to it,
+    /// while its cover comes from the biome it belongs to
+    pub fn column_at(&self, x: i32, z: i32) -> Column {
+        let Some(biomes) = &self.biomes else {
+            return Column {
+                surface: self.settings.height + self.hills(x, z, 1.0, 1.0).round() as i32,
+                cover: BlockID::GROUND,
+                depth: self.settings.ground_depth,
+            };
+        };
+        let weights = biomes.weights_at(x, z);
+        let surface: f32 = weights.iter()
+            .map(|&(index, weight)| {
+                let params = biomes.params(index);
+                weight * (params.height as f32 + self.hills(x, z, params.amplitude, params.scale))
+            })
+            .sum();
+        let (closest, _) = weights[0];
+        Column {
+            surface: self.settings.height + surface.round() as i32,
+            cover: biomes.cover(closest),
+            depth: biomes.params(closest).depth,
+        }
+    }
+    /// the terrain noise at ``x``, ``z``, with ``amplitude`` and ``scale`` times the hills of the settings
+    fn hills(&self, x: i32, z: i32, amplitude: f32, scale: f32) -> f32 {
+        let settings = &self.settings;
+        let noise = fbm(
+            self.seed,
+            Vec2::new(x as f32, z as f32) / (settings.scale * scale),
+            settings.octaves,
+            settings.persistence,
+            settings.lacunarity,
+        );
+        noise * settings.amplitude * amplitude
+    }
+    /// the lowest and the highest the surface can be
+    pub fn surface_range(&self) -> (i32, i32) {
+        let settings = &self.settings;
+        let range = |height: i32, amplitude: f32| {
+            let reach = (settings.amplitude * amplitude).ceil() as i32;
+            (settings.height + height - reach, settings.height + height + reach)
+        };
+        match &self.biomes {
+            Some(biomes) => biomes.settings().biomes.iter()
+                .map(|params| range(params.height, params.amplitude))
+                .fold((i32::MAX, i32::MIN), |(low, high), (bottom, top)| (low.min(bottom), high.max(top))),
+            None => range(0, 1.0),
+        }
+    }
+    /// the most voxels of cover any column has
+    pub fn max_depth(&self) -> i32 {
+        match &self.biomes {
+            Some(biomes) => biomes.settings().biomes.iter().map(|params| params.depth).max().unwrap_or(0),
+            None => self.settings.ground_depth,
+        }
+        .max(0)
+    }
+    /// the block at world height ``y`` of ``column``
+    pub fn block_at(&self, y: i32, column: &Column) -> BlockID {
+        if y < column.surface - column.depth {
+            BlockID::STONE
+        } else if y < column.surface {
+            column.cover
+        } else if y < self.settings.sea_level {
+            BlockID::WATER
+        } else {
+            BlockID::AIR
+        }
+    }
+}
+impl WorldGenerator for TerrainGenerator {
+    fn generate(&self, chunk_pos: IVec3) -> BlockData {
+        let settings = &self.settings;
+        let origin = chunk_pos * CHUNKSIZE as i32;
+        let (bottom, top) = self.surface_range();
+        if origin.y >= top.max(settings.sea_level) {
+            return BlockData::new(ChunkData::filled(BlockID::AIR));
+        }
+        if origin.y + (CHUNKSIZE as i32) <= bottom - self.max_depth() {
+            return BlockData::new(ChunkData::filled(BlockID::STONE));
+        }
+        let mut data = ChunkData::filled(BlockID::AIR);
+        for x in 0..CHUNKSIZE as i32 {
+            for z in 0..CHUNKSIZE as i32 {
+                let column = self.column_at(origin.x + x, origin.z + z);
+                for y in 0..CHUNKSIZE as i32 {
+                    let block = self.block_at(origin.y + y, &column);
+                    if block != BlockID::AIR {
+                        data.set(IVec3::new(x, y, z), block);
+                    }
+                }
+            }
+        }
+        BlockData::new(data)
+    }
+}
+
+/// how ``CaveGenerator`` hollows out the terrain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaveSettings {
+    /// how far 3D noise moves the surface up or down, which makes overhangs and cliffs
+    pub overhang: f32,
+    /// the size of the overhangs, in voxels
+    pub overhang_scale: f32,
+    /// the size of the open caves, in voxels
+    pub cave_scale: f32,
+    /// how much noise it takes to carve a cave, from 0 to 1. higher means fewer caves
+    pub cave_threshold: f32,
+    /// caves stay this many voxels below the surface
+    pub cave_depth: i32,
+    /// the chance for a tunnel to start in each ``WORM_CELL``³ voxels
+    pub worm_chance: f32,
+    /// how many ``WORM_STEP`` voxel steps a tunnel takes
+    pub worm_length: u32,
+    pub worm_radius: f32,
+    /// how far below the lowest surface tunnels can start
+    pub worm_depth: i32,
+}
+impl Default for CaveSettings {
+    fn default() -> Self {
+        Self {
+            overhang: 20.0,
+            overhang_scale: 16.0,
+            cave_scale: 32.0,
+            cave_threshold: 0.45,
+            cave_depth: 6,
+            worm_chance: 0.5,
+            worm_length: 80,
+            worm_radius: 2.5,
+            worm_depth: 96,
+        }
+    }
+}
+
+/// tunnels start at random points in cells of this many voxels
+pub const WORM_CELL: i32 = 64;
+/// how far a tunnel goes with each step
+pub const WORM_STEP: f32 = 2.0;
+
+/// ``TerrainGenerator``'s hills, reshaped by 3D noise into overhangs and cliffs. as a ``StagePass``
+/// it carves caves where another 3D noise is strong, and winding tunnels.
+/// every tunnel is followed from where it starts by each chunk it might reach, so it carves
+/// the same path no matter which of them is generated first
+pub struct CaveGenerator {
+    pub terrain: TerrainGenerator,
+    pub settings: CaveSettings,
+}
+impl CaveGenerator {
+    /// whether ``pos`` is inside the terrain, before caves and tunnels are carved.
+    /// ``surface`` is ``TerrainGenerator::height_at`` for its column
+    pub fn is_solid(&self, pos: IVec3, surface: i32) -> bool {
+        let reach = self.settings.overhang.ceil() as i32;
+        if pos.y < surface - reach { return true; }
+        if pos.y >= surface + reach { return false; }
+        let noise = value_noise_3d(hash(self.terrain.seed, 1, 0), pos.as_vec3() / self.settings.overhang_scale);
+        (surface - pos.y) as f32 + noise * self.settings.overhang > 0.0
+    }
+    /// whether the cave noise carves out ``pos``
+    pub fn is_cave(&self, pos: IVec3) -> bool {
+        value_noise_3d(hash(self.terrain.seed, 2, 0), pos.as_vec3() / self.settings.cave_scale) > self.settings.cave_threshold
+    }
+    /// the range of world heights tunnels start in
+    fn worm_band(&self) -> (i32, i32) {
+        let (bottom, top) = self.terrain.surface_range();
+        (bottom - self.settings.worm_depth, top)
+    }
+    /// carves every tunnel that reaches the chunk at ``chunk_pos``
+    fn carve_worms(&self, chunk_pos: IVec3, data: &mut ChunkData) {
+        let settings = &self.settings;
+        let chunk_min = chunk_pos * CHUNKSIZE as i32;
+        let reach = (settings.worm_length as f32 * WORM_STEP + settings.worm_radius * 2.0).ceil() as i32;
+        let (bottom, top) = self.worm_band();
+        let min = (chunk_min - reach).max(IVec3::new(i32::MIN, bottom, i32::MIN)).div_euclid(IVec3::splat(WORM_CELL));
+        let max = (chunk_min + CHUNKSIZE as i32 + reach).min(IVec3::new(i32::MAX, top, i32::MAX)).div_euclid(IVec3::splat(WORM_CELL));
+        let seed = hash(self.terrain.seed, 3, 0);
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let mut random = SplitMix(hash(hash(seed, x, y), z, 0));
+                    if random.next_f32() >= settings.worm_chance { continue; }
+                    let start = IVec3::new(x, y, z) * WORM_CELL;
+                    let mut pos = start.as_vec3() + Vec3::new(random.next_f32(), random.next_f32(), random.next_f32()) * WORM_CELL as f32;
+                    if !(bottom as f32..top as f32).contains(&pos.y) { continue; }
+                    let mut yaw = random.next_f32() * TAU;
+                    let mut pitch = (random.next_f32() - 0.5) * 0.5;
+                    let phase = random.next_f32() * TAU;
+                    // every step is taken even outside of the chunk, so the random numbers stay in sync
+                    for step in 0..settings.worm_length {
+                        let radius = settings.worm_radius * (1.0 + 0.3 * (step as f32 * 0.2 + phase).sin());
+                        carve_sphere(data, chunk_min, pos, radius);
+                        yaw += (random.next_f32() - 0.5) * 0.6;
+                        pitch = (pitch * 0.9 + (random.next_f32() - 0.5) * 0.3).clamp(-0.8, 0.8);
+                        pos += Vec3::new(yaw.cos() * pitch.cos(), pitch.sin(), yaw.sin() * pitch.cos()) * WORM_STEP;
+                    }
+                }
+            }
+        }
+    }
+}
+impl WorldGenerator for CaveGenerator {
+    fn generate(&self, chunk_pos: IVec3) -> BlockData {
+        let terrain = &self.terrain.settings;
+        let origin = chunk_pos * CHUNKSIZE as i32;
+        let (_, top) = self.terrain.surface_range();
+        if origin.y >= (top + self.settings.overhang.ceil() as i32).max(terrain.sea_level) {
+            return BlockData::new(ChunkData::filled(BlockID::AIR));
+        }
+        let mut data = ChunkData::filled(BlockID::AIR);
+        let mut solid = vec![false; CHUNKSIZE + self.terrain.max_depth() as usize];
+        for x in 0..CHUNKSIZE as i32 {
+            for z in 0..CHUNKSIZE as i32 {
+                let Column { surface, cover, depth } = self.terrain.column_at(origin.x + x, origin.z + z);
+                let depth = depth.max(0) as usize;
+                for (y, solid) in solid[..CHUNKSIZE + depth].iter_mut().enumerate() {
+                    *solid = self.is_solid(origin + IVec3::new(x, y as i32, z), surface);
+                }
+                for y in 0..CHUNKSIZE {
+                    let pos = origin + IVec3::new(x, y as i32, z);
+                    let block = if !solid[y] {
+                        if pos.y < terrain.sea_level { BlockID::WATER } else { BlockID::AIR }
+                    } else if solid[y + 1..=y + depth].iter().all(|solid| *solid) {
+                        BlockID::STONE
+                    } else {
+                        cover
+                    };
+                    if block != BlockID::AIR {
+                        data.set(IVec3::new(x, y as i32, z), block);
+                    }
+                }
+            }
+        }
+        BlockData::new(data)
+    }
+}
+impl StagePass for CaveGenerator {
+    fn stage(&self) -> Stage {
+        Stage::Carve
+    }
+    fn run(&self, chunk_pos: IVec3, data: &mut BlockData, _earlier: &Earlier) {
+        let data = Arc::make_mut(data);
+        let origin = chunk_pos * CHUNKSIZE as i32;
+        let (bottom, top) = self.terrain.surface_range();
+        // caves stay ``cave_depth`` below the surface
+        let depth = self.settings.cave_depth;
+        if origin.y < top - depth && data.uniform() != Some(BlockID::AIR) {
+            let below_surface = origin.y + (CHUNKSIZE as i32) <= bottom - depth;
+            for x in 0..CHUNKSIZE as i32 {
+                for z in 0..CHUNKSIZE as i32 {
+                    let layers = if below_surface {
+                        CHUNKSIZE as i32
+                    } else {
+                        self.terrain.height_at(origin.x + x, origin.z + z) - depth - origin.y
+                    };
+                    for y in 0..layers.min(CHUNKSIZE as i32) {
+                        let local = IVec3::new(x, y, z);
+                        if !matches!(data.get(local), BlockID::AIR | BlockID::WATER) && self.is_cave(origin + local) {
+                            data.set(local, BlockID::AIR);
+                        }
+                    }
+                }
+            }
+        }
+        self.carve_worms(chunk_pos, data);
+    }
+}
+
+/// turns the stone and ground in a sphere around the world position ``center`` into air.
+/// ``chunk_min`` is the world position of the chunk's first voxel
+fn carve_sphere(data: &mut ChunkData, chunk_min: IVec3, center: Vec3, radius: f32) {
+    let min = ((center - radius).floor().as_ivec3() - chunk_min).max(IVec3::ZERO);
+    let max = ((center + radius).ceil().as_ivec3() - chunk_min).min(IVec3::splat(CHUNKSIZE as i32 - 1));
+    if min.cmpgt(max).any() { return; }
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                let local = IVec3::new(x, y, z);
+                let offset = (chunk_min + local).as_vec3() + 0.5 - center;
+                if offset.length_squared() <= radius * radius
+                    && matches!(data.get(local), BlockID::STONE | BlockID::GROUND)
+                {
+                    data.set(local, BlockID::AIR);
+                }
+            }
+        }
+    }
+}
+
+/// the chunks of a world saved in region files, such as a hand built map.
+/// chunks the world doesnt have come from the generator it was saved with, and so do the ones
+/// that are damaged, as the world is only read
+pub struct FileGenerator {
+    template: RegionReader,
+}
+impl FileGenerator {
+    /// opens the world in ``dir``. ``settings`` is only used for what the world doesnt record
+    pub fn open(dir: &str, settings: &WorldSettings, registry: &BlockRegistry) -> io::Result<Self> {
+        Ok(Self { template: RegionReader::open(dir, settings, registry)? })
+    }
+}
+impl WorldGenerator for FileGenerator {
+    fn generate(&self, chunk_pos: IVec3) -> BlockData {
+        match self.template.read(chunk_pos) {
+            Ok(Some(data)) => data,
+            Ok(None) => self.template.generator().generate(chunk_pos),
+            Err(err) => {
+                warn!("couldnt read chunk {chunk_pos} of the template world, generating it: {err}");
+                self.template.generator().generate(chunk_pos)
+            }
+        }
+    }
+    fn cache_bytes(&self) -> usize {
+        self.template.generator().cache_bytes()
+    }
+    fn trim_cache(&self, max_bytes: usize) {
+        self.template.generator().trim_cache(max_bytes);
+    }
+}
+
+/// sets every column of ``data`` from the heights ``surface`` gives, relative to the chunk:
+/// voxels at or above it come from ``above``, and the ones below from ``below``.
+/// both get the voxel's local position
+pub fn fill_columns(
+    data: &mut ChunkData,
+    surface: impl Fn(i32, i32) -> i32,
+    above: impl Fn(IVec3) -> BlockID,
+    below: impl Fn(IVec3) -> BlockID,
+) {
+    for x in 0..CHUNKSIZE as i32 {
+        for z in 0..CHUNKSIZE as i32 {
+            let surface = surface(x, z);
+            for y in 0..CHUNKSIZE as i32 {
+                let local = IVec3::new(x, y, z);
+                let block = if y < surface { below(local) } else { above(local) };
+                if block != data.get(local) {
+                    data.set(local, block);
+                }
+            }
+        }
+    }
+}
+
+/// a random number that only depends on its inputs
+pub fn hash(seed: u64, x: i32, z: i32) -> u64 {
+    mix((seed ^ ((x as u32 as u64) << 32) ^ (z as u32 as u64)).wrapping_add(GOLDEN_GAMMA))
+}
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// the splitmix64 finalizer, which scrambles every bit of ``value`` into every other
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+/// from -1 to 1
+fn unit(value: u64) -> f32 {
+    (value >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+}
+
+/// a small random number generator for things that have to turn out the same every time,
+/// seeded from ``hash``
+pub struct SplitMix(pub u64);
+impl SplitMix {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(GOLDEN_GAMMA);
+        mix(self.0)
+    }
+    /// from 0 to 1
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// smooth noise from -1 to 1, with a random value at every whole coordinate
+pub fn value_noise(seed: u64, x: f32, z: f32) -> f32 {
+    let cell = Vec2::new(x, z).floor();
+    let fraction = Vec2::new(x, z) - cell;
+    let smooth = fraction * fraction * (3.0 - 2.0 * fraction);
+    let corner = |dx: i32, dz: i32| unit(hash(seed, cell.x as i32 + dx, cell.y as i32 + dz));
+    let near = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * smooth.x;
+    let far = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * smooth.x;
+    near + (far - near) * smooth.y
+}
+
+/// ``value_noise`` in three dimensions
+pub fn value_noise_3d(seed: u64, pos: Vec3) -> f32 {
+    let cell = pos.floor();
+    let fraction = pos - cell;
+    let smooth = fraction * fraction * (3.0 - 2.0 * fraction);
+    let cell = cell.as_ivec3();
+    let corner = |dx: i32, dy: i32, dz: i32| unit(hash(hash(seed, cell.x + dx, cell.y + dy), cell.z + dz, 0));
+    let edge = |dy: i32, dz: i32| corner(0, dy, dz) + (corner(1, dy, dz) - corner(0, dy, dz)) * smooth.x;
+    let face = |dz: i32| edge(0, dz) + (edge(1, dz) - edge(0, dz)) * smooth.y;
+    face(0) + (face(1) - face(0)) * smooth.z
+}
+
+/// fractal noise from -1 to 1: ``octaves`` layers of ``value_noise``, each ``lacunarity``
+/// times finer and ``persistence`` times weaker than the one before
+pub fn fbm(seed: u64, pos: Vec2, octaves: u32, persistence: f32, lacunarity: f32) -> f32 {
+    let (mut sum, mut total, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, 1.0);
+    for octave in 0..octaves {
+        // each octave needs its own unrelated noise
+        let seed = hash(seed, octave as i32, 0);
+        sum += value_noise(seed, pos.x * frequency, pos.y * frequency) * amplitude;
+        total += amplitude;
+        amplitude *= persistence;
+        frequency *= lacunarity;
+    }
+    if total > 0.0 { sum / total } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::fast_voxels::{decoration::TreeSettings, ores::VeinSettings};
+
+    /// a checksum of the blocks of ``data``
+    fn chunk_hash(data: &ChunkData) -> u32 {
+        let blocks = data.blocks.to_blocks();
+        let bytes: Vec<u8> = blocks.as_flattened().as_flattened().iter().flat_map(|block| block.0.to_le_bytes()).collect();
+        crc32fast::hash(&bytes)
+    }
+
+    /// across the surface, which lies between y -8 and 72 without biomes, through the caves below it, and down
+    /// among the ores, where coal, copper and oil overlap. away from the origin on every side
+    const POSITIONS: [IVec3; 6] = [
+        IVec3::new(0, 0, 0),
+        IVec3::new(7, 1, -2),
+        IVec3::new(-3, -1, 5),
+        IVec3::new(-40, -2, -25),
+        IVec3::new(12, -3, 9),
+        IVec3::new(-6, -5, -11),
+    ];
+
+    /// the hashes of the chunks at ``POSITIONS``, with caves, the default ores and trees
+    fn hashes(seed: u64, biomes: bool) -> Vec<u32> {
+        let settings = WorldSettings {
+            generator: GeneratorKind::Caves {
+                terrain: TerrainSettings { biomes: biomes.then(BiomeSettings::default), ..default() },
+                caves: CaveSettings::default(),
+            },
+            ores: VeinSettings::defaults(),
+            trees: Some(TreeSettings::default()),
+            structures: Vec::new(),
+            seed,
+            ..default()
+        };
+        let generator = settings.generator.create(&settings, &BlockRegistry::default()).unwrap();
+        POSITIONS.iter()
+            .map(|pos| {
+                let data = generator.generate(*pos);
+                assert_eq!(data.blocks.uniform(), None, "the chunk at {pos} is all one block");
+                chunk_hash(&data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn terrain_matches_the_recorded_chunks() {
+        // saved worlds only store how their chunks differ from these, so they must not change by accident.
+        // if the terrain is meant to change, worlds need a migration and these are recorded again
+        let recorded = [
+            (0, false, [0xa138c56a, 0x3dfa0087, 0x14d33821, 0xb803905a, 0xdf93a233, 0x5ca36f3e]),
+            (0x5eed, false, [0x60b63470, 0x046c7a72, 0x8673fac7, 0xe7d3770e, 0x5116f7a2, 0x7a920376]),
+            (0, true, [0xe77a3a97, 0x0c85664b, 0x334e135c, 0xb803905a, 0xdf93a233, 0x5ca36f3e]),
+            (0x5eed, true, [0x8401ea14, 0x3b047cb6, 0x8673fac7, 0xe7d3770e, 0x5116f7a2, 0x7a920376]),
+        ];
+        for (seed, biomes, chunks) in recorded {
+            // every position checks something else
+            assert_eq!(chunks.iter().collect::<HashSet<_>>().len(), chunks.len());
+            assert_eq!(hashes(seed, biomes), chunks, "seed {seed}, biomes {biomes}");
+        }
+    }
+
+    #[test]
+    fn every_generator_kind_builds_from_its_settings() {
+        let registry = BlockRegistry::default();
+        let kinds = [
+            GeneratorKind::Terrain(TerrainSettings::default()),
+            GeneratorKind::Caves { terrain: TerrainSettings::default(), caves: CaveSettings::default() },
+            GeneratorKind::Flat,
+            GeneratorKind::Checkerboard,
+            GeneratorKind::Noise,
+        ];
+        for kind in kinds {
+            let settings = WorldSettings { generator: kind.clone(), seed: 3, ..default() };
+            let create = || kind.create(&settings, &registry).unwrap_or_else(|err| panic!("{kind:?}: {err}"));
+            let (first, second) = (create(), create());
+            for pos in [IVec3::ZERO, IVec3::new(2, -1, -3)] {
+                assert_eq!(first.generate(pos).blocks.to_blocks(), second.generate(pos).blocks.to_blocks(), "{kind:?} at {pos}");
+            }
+            match kind {
+                GeneratorKind::Flat => assert_eq!(first.generate(IVec3::Y).blocks.uniform(), Some(BlockID::AIR)),
+                GeneratorKind::Checkerboard => assert_eq!(first.generate(IVec3::ZERO).blocks.uniform(), None),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn file_generators_without_a_saved_world_fail() {
+        let settings = WorldSettings {
+            generator: GeneratorKind::File { dir: "no_world_saved_here".to_string() },
+            ..default()
+        };
+        let Err(err) = settings.generator.create(&settings, &BlockRegistry::default()) else {
+            panic!("a file generator opened a world that doesnt exist");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,247 @@
+use std::{fs, sync::Arc};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fast_voxels::{
+        base_types::{CHUNKSIZE, Chunk},
+        biomes::WorldBiomes,
+        block_registry::BlockRegistry,
+        chunk_manager::{ChunkManager, DirtyChunks, GeneratingChunks, Modified},
+        chunk_store::SaveStore,
+        decoration::TreeSettings,
+        neighborhood::MissingNeighbor,
+        ores::VeinSettings,
+        structures::StructureRule,
+        world_gen::{GeneratorKind, WorldGen},
+    },
+    player::camera::Player,
+};
+
+/// which mesher turns chunks into ``VoxelMesh``es
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MeshingBackend {
+    /// one quad per visible voxel face
+    #[default]
+    Naive,
+    /// merges the faces of the same block that lie next to each other into larger quads
+    Greedy,
+}
+
+/// settings for the world around the player. loaded from ``WorldSettings::PATH`` at startup,
+/// and can be changed at runtime, which loads or unloads only the chunks that changed.
+/// changing the seed or what chunks are generated with generates the unchanged chunks again
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldSettings {
+    /// how many chunks are loaded around the player on the x and z axes
+    pub render_distance_hor: i32,
+    /// how many chunks are loaded around the player on the y axis
+    pub render_distance_ver: i32,
+    /// the distances, in chunks, at which each lower level of detail starts
+    pub lod_distances: Vec<u32>,
+    pub meshing: MeshingBackend,
+    /// what meshing takes the chunks that arent loaded yet to be
+    pub missing_neighbors: MissingNeighbor,
+    /// what new chunks are generated with. a saved world has to keep the generator it was made with
+    pub generator: GeneratorKind,
+    /// the deposits placed into generated chunks
+    pub ores: Vec<VeinSettings>,
+    /// the trees and plants placed on the surface, ``None`` leaves it bare
+    pub trees: Option<TreeSettings>,
+    /// the structures placed on the surface, by where they go
+    pub structures: Vec<StructureRule>,
+    /// the seed for world generation
+    pub seed: u64,
+    /// the folder the world's region files are saved in
+    pub save_dir: String,
+    /// seconds between autosaves, 0 turns them off
+    pub autosave_interval: f32,
+}
+impl Default for WorldSettings {
+    fn default() -> Self {
+        Self {
+            render_distance_hor: 2,
+            render_distance_ver: 1,
+            lod_distances: vec![4, 8, 16],
+            meshing: MeshingBackend::Naive,
+            missing_neighbors: MissingNeighbor::Air,
+            generator: GeneratorKind::default(),
+            ores: VeinSettings::defaults(),
+            trees: Some(TreeSettings::default()),
+            structures: Vec::new(),
+            seed: 0,
+            save_dir: "saves/world".to_string(),
+            autosave_interval: 30.0,
+        }
+    }
+}
+impl WorldSettings {
+    pub const PATH: &str = "assets/world_settings.ron";
+
+    /// reads the settings from ``path``, falling back to the defaults if the file
+    /// is missing or invalid
+    pub fn load(path: &str) -> Self {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                warn!("couldnt read world settings from {path}, using defaults: {err}");
+                return Self::default();
+            }
+        };
+        ron::from_str(&text).unwrap_or_else(|err| {
+            warn!("invalid world settings in {path}, using defaults: {err}");
+            Self::default()
+        })
+    }
+    /// how far chunks are loaded from the centre chunk, on each axis
+    pub fn range(&self) -> IVec3 {
+        IVec3::new(
+            self.render_distance_hor,
+            self.render_distance_ver,
+            self.render_distance_hor,
+        )
+    }
+    /// whether the chunk at ``chunk_pos`` is within the render distance of ``center``
+    pub fn in_range(&self, center: IVec3, chunk_pos: IVec3) -> bool {
+        (chunk_pos - center).abs().cmple(self.range()).all()
+    }
+    /// the level of detail the chunk at ``chunk_pos`` is meshed with when ``center`` is the centre chunk,
+    /// one more for every one of ``lod_distances`` it is as far away as
+    pub fn lod(&self, center: IVec3, chunk_pos: IVec3) -> u32 {
+        let distance = (chunk_pos - center).abs().max_element() as u32;
+        self.lod_distances.iter().filter(|start| distance >= **start).count() as u32
+    }
+    /// the settings that decide what new chunks are generated as, apart from the seed
+    pub fn generation(&self) -> GenerationSettings {
+        GenerationSettings {
+            generator: self.generator.clone(),
+            ores: self.ores.clone(),
+            trees: self.trees.clone(),
+            structures: self.structures.clone(),
+        }
+    }
+    /// these settings, generating chunks with ``generation`` and ``seed`` instead
+    pub fn with_generation(&self, generation: GenerationSettings, seed: u64) -> Self {
+        Self {
+            generator: generation.generator,
+            ores: generation.ores,
+            trees: generation.trees,
+            structures: generation.structures,
+            seed,
+            ..self.clone()
+        }
+    }
+}
+
+/// the parts of ``WorldSettings`` that decide what new chunks are generated as, apart from the seed.
+/// a saved world stores the chunks as differences to what they generate, so it records them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenerationSettings {
+    pub generator: GeneratorKind,
+    pub ores: Vec<VeinSettings>,
+    pub trees: Option<TreeSettings>,
+    pub structures: Vec<StructureRule>,
+}
+impl GenerationSettings {
+    /// a checksum of the settings, which stays the same across builds as long as they serialize the same
+    pub fn hash(&self) -> u32 {
+        crc32fast::hash(ron::to_string(self).unwrap_or_default().as_bytes())
+    }
+}
+
+/// the chunk that contains ``translation``
+pub fn chunk_center(translation: Vec3) -> IVec3 {
+    (translation / CHUNKSIZE as f32).floor().as_ivec3()
+}
+
+/// unloads chunks that fell out of the render distance when the settings change, and remeshes
+/// the others when the ``MeshingBackend`` changed. chunks that came into range are loaded by ``manage_chunks``
+pub fn apply_world_settings(
+    mut commands: Commands,
+    settings: Res<WorldSettings>,
+    mut meshing: Local<Option<MeshingBackend>>,
+    mut chunk_manager: ResMut<ChunkManager>,
+    mut dirty: ResMut<DirtyChunks>,
+    mut store: ResMut<SaveStore>,
+    chunks: Query<(&Chunk, Has<Modified>)>,
+    player: Query<&Transform, With<Player>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let remesh = meshing.replace(settings.meshing).is_some_and(|meshing| meshing != settings.meshing);
+    if settings.is_added() {
+        return;
+    }
+    for transform in player {
+        let center = chunk_center(transform.translation);
+        for (chunk, modified) in chunks {
+            if settings.in_range(center, chunk.pos) {
+                if remesh {
+                    dirty.mark(chunk.pos);
+                }
+                continue;
+            }
+            if modified {
+                store.0.save(chunk.pos, &chunk.data);
+            }
+            chunk_manager.remove_chunk(&mut commands, &mut dirty, chunk.pos);
+        }
+    }
+}
+
+/// rebuilds ``WorldGen`` and ``WorldBiomes`` when the seed or the generation settings change,
+/// and generates the loaded chunks that werent changed again. changed chunks keep their blocks,
+/// and the ``SaveStore`` keeps storing chunks as differences to what the world was opened with
+pub fn apply_generation_settings(
+    settings: Res<WorldSettings>,
+    mut applied: Local<Option<(u64, GenerationSettings)>>,
+    registry: Res<BlockRegistry>,
+    mut world_gen: ResMut<WorldGen>,
+    mut biomes: ResMut<WorldBiomes>,
+    mut store: ResMut<SaveStore>,
+    mut generating: ResMut<GeneratingChunks>,
+    chunks: Query<&Chunk, Without<Modified>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let generation = (settings.seed, settings.generation());
+    if applied.as_ref().is_none_or(|applied| *applied == generation) {
+        // the first time, ``WorldGen`` was just built from them
+        *applied = Some(generation);
+        return;
+    }
+    *applied = Some(generation);
+    match settings.generator.create(&settings, &registry) {
+        Ok(generator) => world_gen.0 = generator,
+        Err(err) => {
+            error!("couldnt create the {:?} world generator, keeping the old one: {err}", settings.generator);
+            return;
+        }
+    }
+    *biomes = WorldBiomes::new(&settings, &registry);
+    // dropping the running loads cancels them, and ``manage_chunks`` starts them again
+    generating.0.clear();
+    for chunk in &chunks {
+        generating.0.insert(chunk.pos, store.0.start_load(chunk.pos, Arc::clone(&world_gen.0)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_of_detail_grow_with_the_distance() {
+        let settings = WorldSettings { lod_distances: vec![2, 5], ..default() };
+        let center = IVec3::new(3, -1, 7);
+        let levels = [0, 1, 2, 4, 5, 9].map(|distance| settings.lod(center, center + IVec3::new(0, 0, -distance)));
+        assert_eq!(levels, [0, 0, 1, 1, 2, 2]);
+        // the furthest axis counts
+        assert_eq!(settings.lod(center, center + IVec3::new(1, 5, -2)), 2);
+        assert_eq!(WorldSettings { lod_distances: Vec::new(), ..default() }.lod(center, center + 100), 0);
+    }
+}
@@ -5,13 +5,26 @@
 mod player;
 mod fast_voxels;
 
+use std::path::Path;
+
 use crate::player::camera::{grab_mouse, spawn_player, update_player};
-// use crate::voxel::chunk_manager::{ChunkManager, manage_chunks, poll_mesh_tasks, process_chunks};
+use crate::fast_voxels::{
+    block_registry::BlockRegistry,
+    chunk_plugin::ChunkPlugin,
+    region_store::RegionStore,
+    world_settings::WorldSettings,
+};
 
-// use bevy::platform::collections::HashMap;
 use bevy::prelude::*;
 
-fn main() {
+fn main() -> AppExit {
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("verify-world") {
+        let settings = WorldSettings::load(WorldSettings::PATH);
+        let dir = args.next().unwrap_or_else(|| settings.save_dir.clone());
+        return verify_world(Path::new(&dir), &settings);
+    }
+
     App::new()
         .add_plugins(DefaultPlugins)
         
@@ -20,12 +33,38 @@ fn main() {
         .add_systems(Startup, spawn_player)
         .add_systems(Update, update_player)
 
-        // .insert_resource(ChunkManager { map: HashMap::default(), render_distance_hor: 2, render_distance_ver: 1})
-        
-        // .add_systems(Update, (
-        //     manage_chunks,
-        //     process_chunks,
-        //     poll_mesh_tasks,
-        // ).chain())
-        .run();
-}
\ No newline at end of file
+        .add_plugins(ChunkPlugin)
+        .run()
+}
+
+/// ``verify-world [dir]``: checks every saved chunk of a world and lists the damaged ones.
+/// the world has to use the seed and generator from the settings. fails if anything is damaged
+fn verify_world(dir: &Path, settings: &WorldSettings) -> AppExit {
+    let registry = BlockRegistry::load(BlockRegistry::FILE);
+    let reports = match settings.generator.create(settings, &registry)
+        .and_then(|generator| RegionStore::open(dir, &registry, settings, generator))
+        .and_then(|store| store.verify())
+    {
+        Ok(reports) => reports,
+        Err(err) => {
+            eprintln!("couldnt open the world in {}: {err}", dir.display());
+            return AppExit::error();
+        }
+    };
+    for report in &reports {
+        let path = report.path.display();
+        match &report.error {
+            Some(err) => println!("{path}: unreadable, {err}"),
+            None if report.damaged.is_empty() => println!("{path}: ok, {} chunks", report.chunks),
+            None => {
+                println!("{path}: {} of {} chunks damaged", report.damaged.len(), report.chunks);
+                for (pos, err) in &report.damaged {
+                    println!("    chunk {pos}: {err}");
+                }
+            }
+        }
+    }
+    let damaged = reports.iter().filter(|report| !report.is_intact()).count();
+    println!("{damaged} of {} regions damaged", reports.len());
+    if damaged == 0 { AppExit::Success } else { AppExit::error() }
+}
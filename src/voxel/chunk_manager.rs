@@ -38,7 +38,7 @@ impl ChunkManager {
             return data[local_pos.x as usize][local_pos.y as usize][local_pos.z as usize];
 
         }
-        BlockID::Air
+        BlockID::AIR
     }
     pub fn add_chunk(
         &mut self,
@@ -117,20 +117,20 @@ pub fn manage_chunks(
                     let index: IVec3 = IVec3::new(x,y,z);
                     let exists: bool = chunk_manager.map.contains_key(&index);
                     if !exists {
-                        let mut data = [[[BlockID::Air; 32]; 32]; 32];
+                        let mut data = [[[BlockID::AIR; 32]; 32]; 32];
                         let mut toggle: bool = true;
                         for x in data.iter_mut() {
                             for y in x.iter_mut() {
                                 for z in y.iter_mut() {
                                     if index.y <= 0 {
-                                        *z = BlockID::Stone;
+                                        *z = BlockID::STONE;
                                     } else {
-                                        *z = BlockID::Air;
+                                        *z = BlockID::AIR;
                                     }
                                     // *z = if toggle {
-                                    //     BlockID::Stone
+                                    //     BlockID::STONE
                                     // } else {
-                                    //     BlockID::Air
+                                    //     BlockID::AIR
                                     // };
                                     // toggle = !toggle;
                                 }
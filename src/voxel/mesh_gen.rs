@@ -58,10 +58,10 @@ impl VoxelMesh {
                 for z in 0..Chunk::CHUNKSIZE {
                     let index = IVec3::new(x as i32,y as i32,z as i32);
                     let block_pos = index + chunk_index;
-                    if chunk_manager.get_block(block_pos) == BlockID::Air {continue;}
+                    if chunk_manager.get_block(block_pos) == BlockID::AIR {continue;}
                     
                     for direction in Side::iter() {
-                        if chunk_manager.get_block(block_pos + DIRECTION[direction as usize]) != BlockID::Air {
+                        if chunk_manager.get_block(block_pos + DIRECTION[direction as usize]) != BlockID::AIR {
                             return_val.sides[direction as usize].push_quad(
                                 Quad::from(
                                     Vec3::new(x as f32, y as f32, z as f32),
@@ -100,7 +100,7 @@ impl ChunkManager {
         for x in 0..Chunk::CHUNKSIZE {
             for y in 0..Chunk::CHUNKSIZE {
                 for z in 0..Chunk::CHUNKSIZE {
-                    if chunk.data[x][y][z] == BlockID::Air { continue; }
+                    if chunk.data[x][y][z] == BlockID::AIR { continue; }
 
                     let local_pos = IVec3::new(
                         x as i32 - Chunk::CHUNKSIZE as i32 / 2,
@@ -111,22 +111,22 @@ impl ChunkManager {
                     
                     let world_pos = world_offset + local_pos;
 
-                    if self.get_block(world_pos + IVec3::NEG_X) == BlockID::Air {
+                    if self.get_block(world_pos + IVec3::NEG_X) == BlockID::AIR {
                         ChunkManager::add_quad(mesh_offset, Chunk::LEFTQUAD, &mut vertices, &mut indices);
                     }
-                    if self.get_block(world_pos + IVec3::X) == BlockID::Air {
+                    if self.get_block(world_pos + IVec3::X) == BlockID::AIR {
                         ChunkManager::add_quad(mesh_offset, Chunk::RIGHTQUAD, &mut vertices, &mut indices);
                     }
-                    if self.get_block(world_pos + IVec3::Y) == BlockID::Air {
+                    if self.get_block(world_pos + IVec3::Y) == BlockID::AIR {
                         ChunkManager::add_quad(mesh_offset, Chunk::TOPQUAD, &mut vertices, &mut indices);
                     }
-                    if self.get_block(world_pos + IVec3::NEG_Y) == BlockID::Air {
+                    if self.get_block(world_pos + IVec3::NEG_Y) == BlockID::AIR {
                         ChunkManager::add_quad(mesh_offset, Chunk::BOTTOMQUAD, &mut vertices, &mut indices);
                     }
-                    if self.get_block(world_pos + IVec3::Z) == BlockID::Air {
+                    if self.get_block(world_pos + IVec3::Z) == BlockID::AIR {
                         ChunkManager::add_quad(mesh_offset, Chunk::FRONTQUAD, &mut vertices, &mut indices);
                     }
-                    if self.get_block(world_pos + IVec3::NEG_Z) == BlockID::Air {
+                    if self.get_block(world_pos + IVec3::NEG_Z) == BlockID::AIR {
                         ChunkManager::add_quad(mesh_offset, Chunk::BACKQUAD, &mut vertices, &mut indices);
                     }
                 }
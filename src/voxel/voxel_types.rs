@@ -6,12 +6,7 @@ use bevy::{
 
 pub type BlockData = Arc<[[[BlockID;Chunk::CHUNKSIZE];Chunk::CHUNKSIZE];Chunk::CHUNKSIZE]>;
 
-#[repr(u16)]
-#[derive(PartialEq, Eq, Clone, Copy)]
-pub enum BlockID {
-    Air,
-    Stone,
-}
+pub use crate::fast_voxels::blocks::BlockID;
 #[derive(Component)]
 pub struct NeedsMeshUpdate;
 // #[derive(Component)]